@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::fs::create_dir_all;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::{
+    list_session_names_from_dir, read_session_from_dir, validate_session_name,
+    write_session_to_dir, Session, SessionKeyFormat,
+};
+
+/// Sessions are stored as `scener-sessions/<name>.json` inside the archive.
+pub const ARCHIVE_DIR: &str = "scener-sessions";
+
+/// How many names are generated for an imported session before giving up on a collision.
+const MAX_RENAME_ATTEMPTS: usize = 100;
+
+/// Writes the named sessions in `dir` into a gzip-compressed tarball. Spilled outputs are stored
+/// in the sessions themselves, so that the archive does not refer to files on this host.
+pub fn export_archive_from_dir(dir: &Path, names: &[String], out: impl Write) -> Result<()> {
+    let mut builder = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+    for name in names {
+        let mut session =
+            read_session_from_dir(dir, name).context("could not read session data")?;
+        session.inline_spilled_outputs();
+        let content = serde_json::to_vec(&session).context("could not serialize session")?;
+
+        let entry_name = format!("{}/{}.json", ARCHIVE_DIR, name);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(session.recorded_at.timestamp().max(0) as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, &entry_name, content.as_slice())
+            .with_context(|| format!("could not add session {} to archive", name))?;
+    }
+    builder.into_inner().context("could not write archive")?.finish()?;
+    Ok(())
+}
+
+/// Restores the sessions in an archive into `dir`, and returns their names. A session whose name
+/// is already taken gets a newly generated one.
+pub fn import_archive_to_dir(
+    dir: &Path,
+    input: impl Read,
+    key_format: &SessionKeyFormat,
+) -> Result<Vec<String>> {
+    create_dir_all(dir).with_context(|| format!("could not create directory {}", dir.display()))?;
+    let mut existing: HashSet<String> =
+        list_session_names_from_dir(dir).context("could not list sessions")?.into_iter().collect();
+    let mut archive = tar::Archive::new(GzDecoder::new(input));
+    let mut imported = Vec::new();
+
+    for entry in archive.entries().context("could not read archive")? {
+        let entry = entry.context("could not read archive entry")?;
+        let path = entry.path().context("invalid archive entry")?.into_owned();
+        if !entry.header().entry_type().is_file()
+            || path.extension().is_none_or(|ext| ext != "json")
+        {
+            continue;
+        }
+        let mut session: Session = serde_json::from_reader(entry)
+            .with_context(|| format!("could not parse {}", path.display()))?;
+        validate_session_name(&session.name)
+            .with_context(|| format!("refusing to import {}", path.display()))?;
+        let mut attempts = 0;
+        while existing.contains(&session.name) {
+            if attempts == MAX_RENAME_ATTEMPTS {
+                bail!(
+                    "could not find a free name for session {}; check SCENER_NAME_SUFFIX_LEN",
+                    session.name
+                );
+            }
+            session.regenerate_name(key_format);
+            attempts += 1;
+        }
+        write_session_to_dir(dir, &session).context("could not write session data")?;
+        existing.insert(session.name.clone());
+        imported.push(session.name);
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::remove_file;
+
+    use chrono::DateTime;
+    use tempfile::TempDir;
+
+    use crate::{session_path_in_dir, CommandRecord, CommandStatus};
+
+    use super::*;
+
+    fn test_session(name: &str) -> Session {
+        Session {
+            name: name.into(),
+            recorded_at: DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into(),
+            records: vec![CommandRecord {
+                command: format!("echo {}", name),
+                output: format!("{}\n", name).into_bytes(),
+                status: CommandStatus::Succeeded,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let sessions = vec![test_session("test1"), test_session("test2")];
+        for session in &sessions {
+            write_session_to_dir(dir, session).unwrap();
+        }
+
+        let names = vec!["test1".to_owned(), "test2".to_owned()];
+        let mut archive = Vec::new();
+        export_archive_from_dir(dir, &names, &mut archive).unwrap();
+        for name in &names {
+            remove_file(session_path_in_dir(dir, name)).unwrap();
+        }
+
+        let imported =
+            import_archive_to_dir(dir, archive.as_slice(), &SessionKeyFormat::default()).unwrap();
+        assert_eq!(imported, names);
+        for session in &sessions {
+            assert_eq!(&read_session_from_dir(dir, &session.name).unwrap(), session);
+        }
+    }
+
+    #[test]
+    fn test_archive_import_collision() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        write_session_to_dir(dir, &test_session("test1")).unwrap();
+
+        let mut archive = Vec::new();
+        export_archive_from_dir(dir, &["test1".to_owned()], &mut archive).unwrap();
+        let imported =
+            import_archive_to_dir(dir, archive.as_slice(), &SessionKeyFormat::default()).unwrap();
+
+        assert_eq!(imported.len(), 1);
+        assert_ne!(imported[0], "test1");
+        let session = read_session_from_dir(dir, &imported[0]).unwrap();
+        assert_eq!(session.records, test_session("test1").records);
+    }
+
+    #[test]
+    fn test_archive_spilled_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("sessions");
+        let mut session = test_session("test1");
+        session.spill_outputs(&temp_dir.path().join("spill"), 0).unwrap();
+        write_session_to_dir(&dir, &session).unwrap();
+
+        let mut archive = Vec::new();
+        export_archive_from_dir(&dir, &["test1".to_owned()], &mut archive).unwrap();
+        let other = temp_dir.path().join("other");
+        import_archive_to_dir(&other, archive.as_slice(), &SessionKeyFormat::default()).unwrap();
+
+        let imported = read_session_from_dir(&other, "test1").unwrap();
+        assert_eq!(imported, test_session("test1"));
+    }
+
+    #[test]
+    fn test_archive_import_missing_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        write_session_to_dir(&source, &test_session("test1")).unwrap();
+        let mut archive = Vec::new();
+        export_archive_from_dir(&source, &["test1".to_owned()], &mut archive).unwrap();
+
+        let dir = temp_dir.path().join("fresh");
+        let imported =
+            import_archive_to_dir(&dir, archive.as_slice(), &SessionKeyFormat::default()).unwrap();
+        assert_eq!(imported, vec!["test1".to_owned()]);
+        assert!(session_path_in_dir(&dir, "test1").exists());
+    }
+
+    #[test]
+    fn test_archive_import_traversal() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("sessions");
+        let json = serde_json::to_vec(&test_session("../escaped")).unwrap();
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{}/session.json", ARCHIVE_DIR), json.as_slice())
+            .unwrap();
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        assert!(
+            import_archive_to_dir(&dir, archive.as_slice(), &SessionKeyFormat::default()).is_err()
+        );
+        assert!(!temp_dir.path().join("escaped.json").exists());
+    }
+}
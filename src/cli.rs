@@ -1,40 +1,280 @@
+use std::collections::HashSet;
+use std::fs::File;
 use std::io::stderr;
+use std::io::stdin;
 use std::io::stdout;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use chrono::Utc;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 
 use crate::{
-    execute, list_session_names, needs_newline, print_session, print_session_brief,
-    print_session_script, read_script_from_files, read_script_from_stdin, read_session,
-    remove_session, resolve_references, scan_line, write_session, CommandRecord, CommandStatus,
-    Environment, Session, SessionSummary,
+    build_prompt, default_editor, edit_text, execute, execute_parallel, expand_vars,
+    export_archive_from_dir, format_annotation, format_session_table, get_session_dir,
+    import_archive_to_dir, init_logger, invokes_scener_run, list_session_names_from_dir,
+    merge_sessions, needs_newline, parse_dotenv, parse_menu_selection, parse_reference_list,
+    parse_script_items, play_session, print_session, print_session_brief, print_session_filtered,
+    print_session_grouped, print_session_html, print_session_matches, print_session_script,
+    print_session_tap, read_script, read_script_from_files, read_script_from_stdin,
+    read_session_from_dir, remove_session_from_dir, render_command_menu, resolve_reference,
+    resolve_references, run_hook, scan_line, select_command, session_path_in_dir, strip_prompt,
+    terminal_dimensions, validate_session_name, write_session_to_dir, CommandRecord,
+    CommandRecordSummary, CommandResult, CommandStatus, Environment, ExecOptions, PrintOptions,
+    RenderConfig, ScriptItem, ScriptOptions, SearchQuery, Session, SessionKeyFormat, SessionSource,
+    SessionSummary, Watcher, Wrap, SPILL_THRESHOLD,
 };
+#[cfg(feature = "url")]
+use crate::{confirm_commands, fetch_script, FETCH_TIMEOUT, MAX_SCRIPT_SIZE};
+#[cfg(feature = "clipboard")]
+use crate::{copy_to_clipboard, ClipboardBackend};
+#[cfg(feature = "picker")]
+use crate::{pick_session, PickerEntry, DEFAULT_PROMPT};
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 pub struct RunAction {
     #[arg(short, long)]
     interactive: bool,
-    #[arg(short, long)]
-    unchecked: bool,
-    #[arg(short, long, conflicts_with_all = ["session", "command"])]
+    /// Show the index of the upcoming command in the interactive prompt
+    #[arg(long, requires = "interactive")]
+    prompt_index: bool,
+    /// Run all commands even after one fails
+    #[arg(short, long, alias = "unchecked", short_alias = 'u')]
+    keep_going: bool,
+    /// Run a failing command up to N more times before giving up (not with `--keep-going`)
+    #[arg(long, value_name = "N", default_value = "0", conflicts_with = "keep_going")]
+    retry: u32,
+    /// Wait between retries
+    #[arg(long, value_parser = parse_duration, default_value = "1")]
+    retry_delay: Duration,
+    /// Record every attempt instead of only the last one
+    #[arg(long)]
+    record_retries: bool,
+    /// Discard the environment changes of a failed command
+    #[arg(long)]
+    no_env_forward_on_failure: bool,
+    /// Write outputs larger than 64 KiB to separate files in DIR instead of the session
+    #[arg(long, value_name = "DIR")]
+    spill_output: Option<PathBuf>,
+    /// Record a SHA-256 hash of each command's output
+    #[arg(long)]
+    hash_output: bool,
+    #[arg(short, long, conflicts_with = "session")]
     file: Vec<PathBuf>,
-    #[arg(short, long, conflicts_with_all = ["file", "command"])]
+    #[arg(long, requires = "file")]
+    ignore_missing: bool,
+    /// Run each file that starts with `#!` as a single command under its interpreter
+    #[arg(long, requires = "file")]
+    respect_shebang: bool,
+    /// Download the script from an HTTP(S) URL
+    #[cfg(feature = "url")]
+    #[arg(long, conflicts_with_all = ["file", "session", "retry_failed", "command", "interactive"])]
+    url: Option<String>,
+    /// Run a downloaded script without confirmation
+    #[cfg(feature = "url")]
+    #[arg(long, requires = "url")]
+    yes: bool,
+    #[arg(short, long, conflicts_with = "file")]
     session: Vec<String>,
-    #[arg(conflicts_with_all = ["file", "session"])]
+    /// Rerun only the failed and errored commands of the given session
+    #[arg(long, value_name = "SESSION", conflicts_with_all = ["file", "session", "command"])]
+    retry_failed: Vec<String>,
+    /// Commands to run; with `--file` or `--session`, they run before the commands from there
     command: Vec<String>,
+    /// Read NUL-separated commands from STDIN, so that a command may span multiple lines
+    #[arg(
+        short = '0',
+        long,
+        conflicts_with_all = ["file", "session", "retry_failed", "command", "interactive"]
+    )]
+    null: bool,
+    /// Command to run after all the others; may be given more than once
+    #[arg(long)]
+    also: Vec<String>,
+    /// Limit the address space of each command, e.g. `512M` or `2G`
+    #[arg(long, value_parser = parse_size)]
+    mem_limit: Option<u64>,
+    /// Limit the CPU time of each command, in seconds
+    #[arg(long)]
+    cpu_limit: Option<u64>,
+    /// Start the first command in this directory instead of the current one
+    #[arg(long)]
+    cwd: Option<String>,
+    /// Set an environment variable at the start of the run
+    #[arg(long, value_name = "KEY=VALUE")]
+    env: Vec<String>,
+    /// Load variables from a dotenv-style file; `--env` takes precedence
+    #[arg(long)]
+    env_file: Option<PathBuf>,
+    /// Start the first command with only PATH, HOME and a few other essential variables
+    /// instead of inheriting the whole environment
+    #[arg(long)]
+    clean_env: bool,
+    /// Run at most N commands of a parallel group at once; defaults to the number of CPUs
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+    #[arg(long)]
+    after_hook: Option<String>,
+    #[arg(long, requires = "after_hook")]
+    fail_on_hook_error: bool,
+    #[arg(short, long)]
+    message: Option<String>,
+    /// Kill the running command and skip the rest once the run takes longer than this
+    #[arg(long, value_parser = parse_duration)]
+    deadline: Option<Duration>,
+    /// Fail when a command's output is not valid UTF-8
+    #[arg(long)]
+    strict_utf8: bool,
+    /// Echo `$ command` lines to STDERR so that STDOUT carries only command output
+    #[arg(long)]
+    prompt_stderr: bool,
+    #[arg(long)]
+    allow_nested: bool,
+    /// Strip a leading `$ ` or `# ` prompt (as copied from documentation) from each command
+    #[arg(long, alias = "strip-leading-dollar")]
+    strip_prompts: bool,
+    /// Text prepended to every command when it runs (not recorded)
+    #[arg(long, default_value = "")]
+    prefix: String,
+    /// Text appended to every command when it runs (not recorded)
+    #[arg(long, default_value = "")]
+    suffix: String,
+    /// Run the files again whenever they change
+    #[arg(long, requires = "file", conflicts_with = "interactive")]
+    watch: bool,
+    #[arg(long, requires = "watch")]
+    watch_path: Vec<PathBuf>,
+    #[arg(long, requires = "watch")]
+    watch_overwrite: bool,
+    /// With `tap`, command output goes to STDERR and a TAP report of the recorded session is
+    /// printed to STDOUT
+    #[arg(long, value_enum, default_value = "text", conflicts_with = "watch")]
+    format: RunFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RunFormat {
+    Text,
+    /// Test Anything Protocol
+    Tap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ShowFormat {
+    Text,
+    Html,
+    /// One compact JSON object per session per line
+    Jsonl,
 }
 
 #[derive(Debug, Parser)]
 pub struct ShowAction {
-    #[arg(short, long)]
+    #[arg(long, value_enum, default_value = "text")]
+    format: ShowFormat,
+    #[arg(short, long, conflicts_with = "format")]
     script: bool,
+    #[arg(long, requires = "script")]
+    with_shebang: bool,
+    /// Emit only the commands that succeeded
+    #[arg(long, requires = "script")]
+    only_succeeded: bool,
+    #[arg(long, conflicts_with_all = ["script", "format"])]
+    group_by_status: bool,
+    #[arg(long, conflicts_with = "script")]
+    strip_ansi: bool,
+    /// Wrap output lines at the given column, or at the terminal width if omitted
+    #[arg(
+        long,
+        value_name = "WIDTH",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["script", "format"]
+    )]
+    wrap: Option<Option<u64>>,
+    /// Wrap at exactly the width instead of at word boundaries
+    #[arg(long, requires = "wrap")]
+    wrap_hard: bool,
+    /// Hide the output of commands, except for failed and errored ones
+    #[arg(long, conflicts_with_all = ["script", "format"])]
+    fold: bool,
+    /// Statuses whose output stays visible with `--fold`
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "STATUS", requires = "fold")]
+    unfold_status: Vec<CommandStatus>,
+    #[arg(short, long, conflicts_with_all = ["script", "format", "group_by_status"])]
+    number: bool,
+    #[arg(long)]
+    no_banner: bool,
+    #[arg(long, conflicts_with_all = ["script", "format", "group_by_status", "tail"])]
+    head: Option<usize>,
+    #[arg(long, conflicts_with_all = ["script", "format", "group_by_status"])]
+    tail: Option<usize>,
+    #[cfg(feature = "picker")]
+    #[arg(long, conflicts_with_all = ["session", "count", "from_file"])]
+    pick: bool,
+    /// Show the N most recent sessions
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "session"
+    )]
+    count: Option<u64>,
+    #[arg(long)]
+    strip_common_prefix: bool,
+    /// Show when sessions were recorded relative to now
+    #[arg(long)]
+    relative: bool,
+    /// Rerun the N-th command (as numbered by `--number`) and print both outputs
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["script", "format", "group_by_status", "strip_common_prefix"]
+    )]
+    exec: Option<u64>,
+    /// Record the rerun command as a new session
+    #[arg(long, requires = "exec")]
+    record: bool,
+    /// Replay the session command by command with pauses in between
+    #[arg(
+        long,
+        conflicts_with_all = ["script", "format", "group_by_status", "number", "head", "tail", "exec"]
+    )]
+    play: bool,
+    /// Multiplier for the replay speed; 0 replays without pausing
+    #[arg(long, value_parser = parse_speed, default_value = "1", requires = "play")]
+    speed: f64,
+    /// Pick a command from a numbered menu and print it; `--menu=N` picks the N-th directly
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["script", "format", "group_by_status", "exec", "count"]
+    )]
+    menu: Option<Option<u64>>,
+    /// Write the output to the file instead of STDOUT (the banner still goes to STDERR)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["exec", "menu"])]
+    out: Option<PathBuf>,
+    /// Append to the `--out` file instead of overwriting it
+    #[arg(long, requires = "out")]
+    append: bool,
     #[cfg(feature = "clipboard")]
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "out")]
     copy: bool,
+    /// How to access the clipboard for `--copy`
+    #[cfg(feature = "clipboard")]
+    #[arg(long, value_enum, default_value = "arboard", requires = "copy")]
+    clipboard: ClipboardBackend,
+    /// Read session references from the file, one per line
+    #[arg(long, value_name = "FILE", conflicts_with = "count")]
+    from_file: Option<PathBuf>,
     session: Vec<String>,
 }
 
@@ -44,128 +284,697 @@ pub struct ListAction {
     full: bool,
     #[arg(short, long, short_alias = 'n', default_value = "10")]
     limit: usize,
+    #[arg(long)]
+    no_truncate: bool,
+    #[arg(long, conflicts_with_all = ["full", "limit", "no_truncate"])]
+    count_only: bool,
+    /// Show when sessions were recorded relative to now
+    #[arg(long)]
+    relative: bool,
+    /// Show the first line of each command's output
+    #[arg(long, conflicts_with = "count_only")]
+    preview: bool,
+    /// Hide sessions whose first command matches the glob
+    #[arg(long, value_name = "GLOB")]
+    exclude_command: Vec<String>,
+    /// List only the sessions recorded after the given one
+    #[arg(long, value_name = "SESSION")]
+    since_session: Option<String>,
+    /// Show one line per session in a table
+    #[arg(long, conflicts_with_all = ["count_only", "full", "preview"])]
+    columns: bool,
 }
 
 #[derive(Debug, Parser)]
 pub struct RemoveAction {
+    #[arg(long, conflicts_with = "from_file")]
+    all: bool,
+    /// Read session references from the file, one per line
+    #[arg(long, value_name = "FILE")]
+    from_file: Option<PathBuf>,
+    session: Vec<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct SearchAction {
+    #[arg(short = 'C', long, default_value = "0")]
+    context: usize,
+    #[arg(short = 'v', long)]
+    invert: bool,
     #[arg(long)]
+    command_only: bool,
+    /// Print the full output of matching commands instead of the matched lines
+    #[arg(long, conflicts_with = "context")]
+    all_output: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct MergeAction {
+    #[arg(long)]
+    name: Option<String>,
+    #[arg(long)]
+    newest: bool,
+    #[arg(long)]
+    remove_sources: bool,
+    #[arg(required = true)]
+    session: Vec<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct WhichAction {
+    #[arg(required = true)]
+    session: Vec<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct DiffAction {
+    /// Compare the sets of commands, ignoring their order and outputs
+    #[arg(long, required = true)]
+    set: bool,
+    left: String,
+    right: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct RerunAction {
+    session: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct DescribeAction {
+    session: String,
+    text: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct NoteAction {
+    session: String,
+    text: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct ExportAction {
+    #[arg(long, conflicts_with = "session")]
     all: bool,
+    /// Path of the gzip-compressed tarball to write
+    #[arg(long)]
+    out: PathBuf,
+    #[arg(required_unless_present = "all")]
     session: Vec<String>,
 }
 
+#[derive(Debug, Parser)]
+pub struct ImportAction {
+    /// Path of a tarball written by `scener export`
+    #[arg(long)]
+    archive: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct VerifyAction {
+    /// Rename files whose name does not match the session name they contain
+    #[arg(long)]
+    fix: bool,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Action {
-    Run(RunAction),
+    Run(Box<RunAction>),
     Show(ShowAction),
     #[command(alias = "ls")]
     List(ListAction),
     #[command(alias = "rm")]
     Remove(RemoveAction),
+    Search(SearchAction),
+    Merge(MergeAction),
+    Which(WhichAction),
+    Diff(DiffAction),
+    Describe(DescribeAction),
+    Note(NoteAction),
+    Rerun(RerunAction),
+    Export(ExportAction),
+    Import(ImportAction),
+    Verify(VerifyAction),
 }
 
 #[derive(Debug, Parser)]
 pub struct Cli {
+    #[arg(long, global = true)]
+    pub plain: bool,
+    #[arg(long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
     #[command(subcommand)]
     pub action: Action,
 }
 
-fn collect_commands(sessions: &[SessionSummary]) -> Vec<String> {
-    sessions.iter().flat_map(|session| session.records.iter().map(|r| r.command.clone())).collect()
+fn parse_size(s: &str) -> Result<u64> {
+    let (digits, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let n: u64 = digits.parse().with_context(|| format!("invalid size: {}", s))?;
+    let shift = match unit {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" | "m" => 20,
+        "G" | "g" => 30,
+        _ => bail!("invalid size unit: {}", unit),
+    };
+    n.checked_mul(1 << shift).with_context(|| format!("size too large: {}", s))
+}
+
+fn parse_duration(s: &str) -> Result<Duration> {
+    let (digits, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let n: u64 = digits.parse().with_context(|| format!("invalid duration: {}", s))?;
+    let secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => bail!("invalid duration unit: {}", unit),
+    };
+    let secs = n.checked_mul(secs).with_context(|| format!("duration too large: {}", s))?;
+    Ok(Duration::from_secs(secs))
+}
+
+fn parse_speed(s: &str) -> Result<f64> {
+    let speed: f64 = s.parse().with_context(|| format!("invalid speed: {}", s))?;
+    if !speed.is_finite() || speed < 0.0 {
+        bail!("speed must be a non-negative number: {}", s);
+    }
+    Ok(speed)
+}
+
+fn expand_arg(s: &str) -> String {
+    let home = std::env::var("HOME").ok();
+    let (expanded, undefined) = expand_vars(s, home.as_deref(), |name| std::env::var(name).ok());
+    for name in undefined {
+        eprintln!("warning: variable {} is not defined; expanded to empty", name);
+    }
+    expanded
+}
+
+fn env_overrides(env_file: Option<&Path>, env_args: &[String]) -> Result<Vec<(String, String)>> {
+    let explicit = env_args
+        .iter()
+        .map(|arg| match arg.split_once('=') {
+            Some((key, value)) => Ok((key.to_owned(), expand_arg(value))),
+            None => bail!("invalid `--env` argument (expected KEY=VALUE): {}", arg),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut overrides = match env_file {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("could not read env file {}", path.display()))?;
+            parse_dotenv(&content)
+                .with_context(|| format!("could not parse env file {}", path.display()))?
+        }
+        None => Vec::new(),
+    };
+    overrides.retain(|(key, _)| explicit.iter().all(|(k, _)| k != key));
+    overrides.extend(explicit);
+    Ok(overrides)
+}
+
+fn initial_environment(
+    cwd: Option<&str>,
+    env_file: Option<&Path>,
+    env_args: &[String],
+    clean_env: bool,
+) -> Result<Environment> {
+    let work_dir = cwd.map(expand_arg);
+    let overrides = env_overrides(env_file, env_args)?;
+    Ok(Environment::initial(work_dir, overrides, clean_env, std::env::vars()))
+}
+
+fn collect_commands_by<F: Fn(&CommandRecordSummary) -> bool>(
+    sessions: &[SessionSummary],
+    predicate: F,
+) -> Vec<String> {
+    let command = |r: &CommandRecordSummary| match r.status {
+        CommandStatus::Annotation => format_annotation(&r.command),
+        _ => r.command.clone(),
+    };
+    sessions
+        .iter()
+        .flat_map(|session| session.records.iter().filter(|r| predicate(r)).map(command))
+        .collect()
 }
 
 fn lookup_commands<I: IntoIterator<Item = S>, S: AsRef<str>>(
+    dir: &Path,
     references: I,
     session_names: &[String],
 ) -> Result<Vec<String>> {
-    let resolved =
+    lookup_commands_by(dir, references, session_names, |_| true)
+}
+
+fn lookup_commands_by<I: IntoIterator<Item = S>, S: AsRef<str>, F>(
+    dir: &Path,
+    references: I,
+    session_names: &[String],
+    predicate: F,
+) -> Result<Vec<String>>
+where
+    F: Fn(&CommandRecordSummary) -> bool,
+{
+    let mut resolved =
         resolve_references(references, session_names).context("could not resolve references")?;
+    // a session referenced more than once (e.g. `@1 test1`) contributes its commands only once
+    let mut seen = HashSet::new();
+    resolved.retain(|name| seen.insert(name.clone()));
     let sessions = resolved
         .into_iter()
         .map(|name| {
-            read_session(&name)
-                .map(|session| session.summary())
+            read_session_from_dir(dir, &name)
+                .map(|session| session.summary(false))
                 .with_context(|| format!("could not read session {}", name))
         })
         .collect::<Result<Vec<SessionSummary>>>()?;
-    Ok(collect_commands(&sessions))
+    Ok(collect_commands_by(&sessions, predicate))
 }
 
-fn run_command(env: Environment, command: String) -> Result<(Environment, CommandRecord, bool)> {
-    println!("$ {}", command);
-
-    let result = execute(&command, env, &mut stdout().lock())
-        .with_context(|| format!("could not execute command {}", command))?;
+fn build_record(
+    command: String,
+    result: CommandResult,
+    options: &ExecOptions,
+) -> (Environment, CommandRecord) {
+    let CommandResult { new_env, output, succeeded, invalid_utf8_at } = result;
 
-    if needs_newline(&result.output) {
-        println!();
+    if let Some(at) = invalid_utf8_at {
+        eprintln!("error: command output is not valid UTF-8 (at byte {})", at);
     }
 
-    let status = match result.succeeded {
+    let status = match succeeded {
         true => CommandStatus::Succeeded,
         false => CommandStatus::Failed,
     };
-    let record = CommandRecord { command, output: result.output, status };
+
+    if !succeeded && options.is_limited() {
+        eprintln!("command failed under resource limits; it may have exceeded a limit");
+    }
+
+    let work_dir = new_env.work_dir().map(ToOwned::to_owned);
+    (new_env, CommandRecord { command, output, status, work_dir, ..Default::default() })
+}
+
+#[derive(Debug, Default)]
+struct CommandWrapper {
+    prefix: String,
+    suffix: String,
+}
+
+#[derive(Debug, Default)]
+struct RetryPolicy {
+    count: u32,
+    delay: Duration,
+    record_all: bool,
+}
+
+impl CommandWrapper {
+    // `execute` installs its env-capturing trap before the whole string, so the wrapper runs
+    // inside the trapped region as well
+    fn wrap(&self, command: &str) -> String {
+        format!("{}{}{}", self.prefix, command, self.suffix)
+    }
+}
+
+/// Writes `$ command` lines (and the blank lines around them) to `prompt_out`, or to `out` if
+/// it is `None`.
+fn write_prompt(
+    out: &mut impl Write,
+    prompt_out: &mut Option<&mut dyn Write>,
+    command: Option<&str>,
+) -> std::io::Result<()> {
+    let out: &mut dyn Write = match prompt_out {
+        Some(prompt_out) => *prompt_out,
+        None => out,
+    };
+    match command {
+        Some(command) => writeln!(out, "$ {}", command),
+        None => writeln!(out),
+    }
+}
+
+fn run_command(
+    env: Environment,
+    command: String,
+    wrapper: &CommandWrapper,
+    options: &ExecOptions,
+    mut out: impl Write,
+    mut prompt_out: Option<&mut dyn Write>,
+) -> Result<(Environment, CommandRecord, bool)> {
+    write_prompt(&mut out, &mut prompt_out, Some(&command))?;
+
+    let result = execute(&wrapper.wrap(&command), env, options, &mut out)
+        .with_context(|| format!("could not execute command {}", command))?;
+    if needs_newline(&String::from_utf8_lossy(&result.output)) {
+        writeln!(&mut out)?;
+    }
+
+    let (new_env, record) = build_record(command, result, options);
     let ok = record.status.is_succeeded();
 
-    Ok((result.new_env, record, ok))
+    Ok((new_env, record, ok))
+}
+
+/// Runs `command` like `run_command`, running it again from the same `env` while it fails, up to
+/// `retry.count` more times or until the deadline passes.
+#[allow(clippy::too_many_arguments)]
+fn run_command_with_retry(
+    env: Environment,
+    command: String,
+    wrapper: &CommandWrapper,
+    options: &ExecOptions,
+    retry: &RetryPolicy,
+    mut sleep: impl FnMut(Duration),
+    mut out: impl Write,
+    mut prompt_out: Option<&mut dyn Write>,
+) -> Result<(Environment, Vec<CommandRecord>, bool)> {
+    let mut records = Vec::new();
+    let mut attempt = 0;
+    loop {
+        let (new_env, record, ok) = run_command(
+            env.clone(),
+            command.clone(),
+            wrapper,
+            options,
+            &mut out,
+            prompt_out.as_mut().map(|p| &mut **p as &mut dyn Write),
+        )?;
+        let expired = options.deadline.is_some_and(|d| Instant::now() >= d);
+        if ok || attempt == retry.count || expired {
+            records.push(record);
+            return Ok((new_env, records, ok));
+        }
+        if retry.record_all {
+            records.push(record);
+        }
+        attempt += 1;
+        eprintln!("command failed; retrying ({}/{})", attempt, retry.count);
+        sleep(retry.delay);
+    }
+}
+
+// Commands in a parallel group all start from `env`, and their environment changes are discarded.
+fn run_parallel(
+    env: Environment,
+    commands: Vec<String>,
+    jobs: usize,
+    wrapper: &CommandWrapper,
+    options: &ExecOptions,
+    mut out: impl Write,
+    mut prompt_out: Option<&mut dyn Write>,
+) -> Result<(Environment, Vec<CommandRecord>, bool)> {
+    let wrapped: Vec<String> = commands.iter().map(|c| wrapper.wrap(c)).collect();
+    let results = execute_parallel(&wrapped, &env, options, jobs);
+
+    let mut records = Vec::new();
+    let mut ok = true;
+
+    for (index, (command, result)) in commands.into_iter().zip(results).enumerate() {
+        if index > 0 {
+            write_prompt(&mut out, &mut prompt_out, None)?;
+        }
+        write_prompt(&mut out, &mut prompt_out, Some(&command))?;
+        let result = result.with_context(|| format!("could not execute command {}", command))?;
+        out.write_all(&result.output)?;
+        if needs_newline(&String::from_utf8_lossy(&result.output)) {
+            writeln!(&mut out)?;
+        }
+
+        let (_, mut record) = build_record(command, result, options);
+        // every command of the group starts from the same directory, whatever it cds into
+        record.work_dir = env.work_dir().map(ToOwned::to_owned);
+        ok = ok && record.status.is_succeeded();
+        records.push(record);
+    }
+
+    Ok((env, records, ok))
 }
 
-pub fn run(action: RunAction) -> Result<()> {
+fn read_commands(dir: &Path, action: &RunAction) -> Result<(Vec<String>, SessionSource)> {
     let RunAction {
         interactive,
-        unchecked,
         file: file_args,
+        ignore_missing,
+        respect_shebang,
         session: session_args,
+        retry_failed: retry_args,
         command: command_args,
+        null,
+        also: also_args,
+        ..
     } = action;
 
-    let checked = !unchecked;
+    #[cfg(feature = "url")]
+    if let Some(url) = &action.url {
+        let mut commands = fetch_script(url, FETCH_TIMEOUT, MAX_SCRIPT_SIZE)
+            .context("could not read script from URL")?;
+        commands.extend(also_args.iter().cloned());
+        if !action.yes {
+            if !stdin().is_terminal() {
+                bail!("refusing to run a downloaded script without `--yes`");
+            }
+            let read_line = || {
+                let mut line = String::new();
+                let n = stdin().read_line(&mut line).context("could not read answer")?;
+                Ok((n > 0).then_some(line))
+            };
+            if !confirm_commands(&commands, read_line, stderr())? {
+                bail!("aborted");
+            }
+        }
+        return Ok((commands, SessionSource::Url(url.clone())));
+    }
+
     let from_file = !file_args.is_empty();
     let from_session = !session_args.is_empty();
+    let from_failures = !retry_args.is_empty();
     let from_command = !command_args.is_empty();
 
-    let commands = if from_file {
-        read_script_from_files(file_args.iter()).context("could not read script from file")?
+    // command arguments come first, then the commands of `--file` or `--session`
+    let (script, source) = if from_file {
+        let files = read_script_from_files(file_args.iter(), *ignore_missing)
+            .context("could not read script from file")?;
+        let script =
+            files.into_iter().flat_map(|file| file.into_commands(*respect_shebang)).collect();
+        (script, SessionSource::File(file_args.clone()))
     } else if from_session {
-        let session_names = list_session_names().context("could not list sessions")?;
-        lookup_commands(session_args.iter(), &session_names).context("could not lookup commands")?
+        let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+        let script = lookup_commands(dir, session_args.iter(), &session_names)
+            .context("could not lookup commands")?;
+        (script, SessionSource::Session(session_args.clone()))
+    } else if from_failures {
+        let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+        let failed = |r: &CommandRecordSummary| r.status.is_failed_or_errored();
+        let script = lookup_commands_by(dir, retry_args.iter(), &session_names, failed)
+            .context("could not lookup commands")?;
+        (script, SessionSource::Session(retry_args.clone()))
     } else if from_command {
-        command_args
+        (Vec::new(), SessionSource::Command)
     } else if !interactive {
-        read_script_from_stdin().context("could not read script from STDIN")?
+        let script = read_script_from_stdin(*null)?;
+        (script, SessionSource::Stdin)
     } else {
-        Vec::new()
+        (Vec::new(), SessionSource::Interactive)
+    };
+
+    let mut commands = command_args.clone();
+    commands.extend(script);
+    commands.extend(also_args.iter().cloned());
+
+    Ok((commands, source))
+}
+
+fn skipped_records(items: impl Iterator<Item = ScriptItem>) -> Vec<CommandRecord> {
+    let mut records = Vec::new();
+    for item in items {
+        let commands = match item {
+            ScriptItem::Command(command) => vec![command],
+            ScriptItem::Parallel(commands) => commands,
+            ScriptItem::Annotation(text) => {
+                records.push(CommandRecord::annotation(text));
+                continue;
+            }
+        };
+        records.extend(commands.into_iter().map(|command| CommandRecord {
+            command,
+            status: CommandStatus::Skipped,
+            ..Default::default()
+        }));
+    }
+    records
+}
+
+fn run_once(
+    dir: &Path,
+    action: RunAction,
+    key_format: &SessionKeyFormat,
+    out: impl Write,
+) -> Result<(String, bool)> {
+    let (commands, source) = read_commands(dir, &action)?;
+    run_commands(dir, commands, source, action, key_format, scan_line, terminal_dimensions(), out)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_commands(
+    dir: &Path,
+    commands: Vec<String>,
+    source: SessionSource,
+    action: RunAction,
+    key_format: &SessionKeyFormat,
+    mut read_line: impl FnMut(&str) -> Result<Option<String>>,
+    tty_size: Option<(u16, u16)>,
+    mut out: impl Write,
+) -> Result<(String, bool)> {
+    let strip_prompts = action.strip_prompts;
+    let commands: Vec<String> = match strip_prompts {
+        true => commands.iter().map(|c| strip_prompt(c)).collect(),
+        false => commands,
+    };
+    if !action.allow_nested {
+        for command in commands.iter().filter(|c| invokes_scener_run(c)) {
+            eprintln!("warning: command `{}` runs scener recursively", command);
+        }
+    }
+    let items = parse_script_items(commands).context("could not parse script")?;
+    let RunAction {
+        interactive,
+        prompt_index,
+        keep_going,
+        retry,
+        retry_delay,
+        record_retries,
+        no_env_forward_on_failure,
+        spill_output,
+        hash_output,
+        mem_limit,
+        cpu_limit,
+        cwd,
+        env: env_args,
+        env_file,
+        clean_env,
+        jobs,
+        after_hook,
+        fail_on_hook_error,
+        message,
+        deadline,
+        strict_utf8,
+        prompt_stderr,
+        prefix,
+        suffix,
+        ..
+    } = action;
+
+    let wrapper = CommandWrapper { prefix, suffix };
+    let retry = RetryPolicy { count: retry, delay: retry_delay, record_all: record_retries };
+    let deadline = deadline.map(|d| Instant::now() + d);
+    let options = ExecOptions { mem_limit, cpu_limit, deadline, strict_utf8 };
+    let jobs = match jobs {
+        Some(jobs) => jobs as usize,
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
     };
 
     let mut terminated = false;
-    let mut env = Environment::default();
+    let mut env = initial_environment(cwd.as_deref(), env_file.as_deref(), &env_args, clean_env)
+        .context("could not construct initial environment")?;
+    // without `--cwd`, bash starts in the directory it inherits through PWD
+    let start_dir = env.work_dir().map(ToOwned::to_owned).or_else(|| std::env::var("PWD").ok());
     let mut records = Vec::new();
 
-    let mut iter = commands.into_iter();
+    let mut iter = items.into_iter();
+    let mut prompt_err = stderr();
 
     loop {
-        let command = match iter.next() {
-            Some(c) => c,
+        let item = match iter.next() {
+            Some(item) => item,
             None => {
                 if !interactive {
                     break;
                 }
-                match scan_line()? {
-                    Some(c) => c,
-                    None => break,
+                let prompt = build_prompt(prompt_index.then_some(records.len() + 1));
+                match read_line(&prompt) {
+                    Ok(Some(c)) if strip_prompts => ScriptItem::Command(strip_prompt(&c)),
+                    Ok(Some(c)) => ScriptItem::Command(c),
+                    Ok(None) => break,
+                    // keep what has been recorded so far
+                    Err(err) => {
+                        eprintln!("error: could not read command: {:#}", err);
+                        break;
+                    }
                 }
             }
         };
 
-        if interactive && command.trim() == "!" {
-            records.pop();
-            continue;
-        }
+        let previous_env = no_env_forward_on_failure.then(|| env.clone());
+        let (e, rs, ok) = match item {
+            ScriptItem::Annotation(text) => {
+                records.push(CommandRecord::annotation(text));
+                continue;
+            }
+            ScriptItem::Command(command) if interactive && command.trim() == "!" => {
+                records.pop();
+                continue;
+            }
+            ScriptItem::Command(command) if interactive => {
+                // an execution error is recorded instead of discarding the interactive session
+                let prompt_out = prompt_stderr.then_some(&mut prompt_err as &mut dyn Write);
+                let result = run_command_with_retry(
+                    env.clone(),
+                    command.clone(),
+                    &wrapper,
+                    &options,
+                    &retry,
+                    std::thread::sleep,
+                    &mut out,
+                    prompt_out,
+                );
+                match result {
+                    Ok(result) => result,
+                    Err(err) => {
+                        eprintln!("error: {:#}", err);
+                        records.push(CommandRecord::errored(command));
+                        continue;
+                    }
+                }
+            }
+            ScriptItem::Command(command) => {
+                let prompt_out = prompt_stderr.then_some(&mut prompt_err as &mut dyn Write);
+                run_command_with_retry(
+                    env,
+                    command,
+                    &wrapper,
+                    &options,
+                    &retry,
+                    std::thread::sleep,
+                    &mut out,
+                    prompt_out,
+                )?
+            }
+            ScriptItem::Parallel(commands) => {
+                let prompt_out = prompt_stderr.then_some(&mut prompt_err as &mut dyn Write);
+                run_parallel(env, commands, jobs, &wrapper, &options, &mut out, prompt_out)?
+            }
+        };
+        env = match previous_env {
+            Some(previous_env) if !ok => previous_env,
+            _ => e,
+        };
+        // invalid output stops the run even with `--keep-going`
+        let invalid_utf8 =
+            options.strict_utf8 && rs.iter().any(|r| std::str::from_utf8(&r.output).is_err());
+        records.extend(rs);
+        terminated = terminated || (!keep_going && !ok) || invalid_utf8;
 
-        let (e, r, ok) = run_command(env, command)?;
-        env = e;
-        records.push(r);
-        terminated = terminated || (checked && !ok);
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            eprintln!("run deadline exceeded; remaining commands are skipped");
+            terminated = true;
+        }
 
         if terminated {
             break;
@@ -176,133 +985,1678 @@ pub fn run(action: RunAction) -> Result<()> {
         }
 
         if iter.len() > 0 || interactive {
-            println!();
+            let mut prompt_out = prompt_stderr.then_some(&mut prompt_err as &mut dyn Write);
+            write_prompt(&mut out, &mut prompt_out, None)?;
         }
     }
 
-    for command in iter {
-        records.push(CommandRecord {
-            command,
-            output: Default::default(),
-            status: CommandStatus::Skipped,
-        });
+    if hash_output {
+        for record in records.iter_mut().filter(|r| r.status.is_executed()) {
+            record.output_hash = Some(record.compute_output_hash());
+        }
     }
+    records.extend(skipped_records(iter));
 
-    let session = Session::new(Utc::now(), records);
-    write_session(&session).context("could not write session data")?;
+    let mut session = Session::new(Utc::now(), records, key_format);
+    session.make_name_unique_in(dir);
+    session.description = message;
+    session.source = Some(source);
+    session.tty_size = tty_size;
+    session.work_dir = start_dir;
+    if let Some(spill_dir) = spill_output {
+        // the reference must stay valid when `show` runs from another directory
+        let spill_dir =
+            std::path::absolute(&spill_dir).context("could not resolve spill directory")?;
+        session.spill_outputs(&spill_dir, SPILL_THRESHOLD).context("could not spill output")?;
+    }
+    write_session_to_dir(dir, &session).context("could not write session data")?;
     eprintln!("\nsession {} recorded", session.name);
 
-    if terminated {
-        bail!("command exited with non-zero exit code");
+    if let Some(hook) = after_hook {
+        let path = session_path_in_dir(dir, &session.name);
+        let result = run_hook(&hook, &session.name, &path).and_then(|ok| match ok {
+            true => Ok(()),
+            false => Err(anyhow!("after-hook exited with non-zero exit code")),
+        });
+        if let Err(err) = result {
+            if fail_on_hook_error {
+                return Err(err.context("could not run after-hook"));
+            }
+            eprintln!("warning: could not run after-hook: {:#}", err);
+        }
     }
-    Ok(())
+
+    Ok((session.name, !terminated))
 }
 
-pub fn show_to(references: &[String], script: bool, mut out: impl Write) -> Result<()> {
-    let mut iter = references.iter();
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+/// Files that keep changing trigger a re-run this long after the first change.
+const WATCH_SETTLE: Duration = Duration::from_secs(2);
 
-    while let Some(reference) = iter.next() {
-        let session = read_session(reference).context("could not read session data")?;
-        if script {
-            print_session_script(session, &mut out, stderr()).context("could not print output")?;
-        } else {
-            print_session(session, &mut out, stderr()).context("could not print output")?;
-        }
-        if iter.len() > 0 {
-            writeln!(&mut out)?;
+fn run_watch(
+    dir: &Path,
+    action: RunAction,
+    key_format: &SessionKeyFormat,
+    mut out: impl Write,
+) -> Result<()> {
+    let paths = action.file.iter().chain(action.watch_path.iter()).cloned().collect();
+    let mut watcher = Watcher::new(paths);
+    let mut previous: Option<String> = None;
+
+    loop {
+        // each run starts over from the initial environment
+        let recorded = match run_once(dir, action.clone(), key_format, &mut out) {
+            Ok((name, ok)) => {
+                if !ok {
+                    eprintln!("error: command exited with non-zero exit code");
+                }
+                Some(name)
+            }
+            Err(err) => {
+                eprintln!("error: {:#}", err);
+                None
+            }
+        };
+        if action.watch_overwrite {
+            if let Some(name) = previous.take().filter(|p| recorded.as_ref() != Some(p)) {
+                remove_session_from_dir(dir, &name).context("could not remove previous session")?;
+            }
+            previous = recorded;
         }
+
+        eprintln!("\nwatching for changes (press Ctrl-C to exit)");
+        let changed = watcher.wait(WATCH_DEBOUNCE, WATCH_SETTLE);
+        let changed: Vec<String> = changed.iter().map(|p| p.display().to_string()).collect();
+        eprintln!("\n---- {} changed; re-running ----\n", changed.join(", "));
     }
+}
 
+/// Runs commands as `run` does, but streams the live output of the commands into `out`.
+pub fn run_to(action: RunAction, key_format: &SessionKeyFormat, mut out: impl Write) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    if action.watch {
+        return run_watch(&session_dir, action, key_format, out);
+    }
+    let format = action.format;
+    let ok = match format {
+        RunFormat::Text => run_once(&session_dir, action, key_format, &mut out)?.1,
+        RunFormat::Tap => {
+            let (name, ok) = run_once(&session_dir, action, key_format, stderr())?;
+            let session = read_session_from_dir(&session_dir, &name)
+                .context("could not read session data")?;
+            print_session_tap(session, &mut out).context("could not print output")?;
+            ok
+        }
+    };
+    if !ok {
+        bail!("command exited with non-zero exit code");
+    }
     Ok(())
 }
 
-pub fn show(action: ShowAction) -> Result<()> {
-    let ShowAction { script, session: reference_args, .. } = action;
+pub fn run(action: RunAction, key_format: &SessionKeyFormat) -> Result<()> {
+    run_to(action, key_format, stdout())
+}
 
-    let session_names = list_session_names().context("could not list sessions")?;
-    if session_names.is_empty() {
-        bail!("no sessions recorded");
-    }
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ShowMode {
+    Normal,
+    Grouped,
+    Script(ScriptOptions),
+    Html,
+    JsonLines,
+    Play { speed: f64 },
+}
 
-    let latest = session_names[0].clone();
-    let references: Vec<String> = match reference_args.is_empty() {
-        true => vec![latest],
-        false => resolve_references(&reference_args, &session_names)
-            .context("invalid `--session` argument")?,
+/// Returns the length of the longest sequence of leading commands shared by all lists.
+fn common_prefix_len(commands: &[Vec<String>]) -> usize {
+    let Some((first, rest)) = commands.split_first() else {
+        return 0;
     };
-
-    #[cfg(feature = "clipboard")]
-    if action.copy {
-        let mut cursor = std::io::Cursor::new(Vec::new());
-        show_to(&references, script, &mut cursor)?;
-        let buffer = cursor.into_inner();
-        let text = String::from_utf8_lossy(&buffer);
-        let len = text.len();
-        arboard::Clipboard::new()
-            .and_then(|mut cb| cb.set_text(text))
-            .context("could not set text to clipboard")?;
-        eprintln!("{} chars copied into clipboard", len);
-        return Ok(());
-    }
-
-    show_to(&references, script, stdout())
+    first
+        .iter()
+        .enumerate()
+        .take_while(|(i, command)| rest.iter().all(|other| other.get(*i) == Some(*command)))
+        .count()
 }
 
-pub fn list(action: ListAction) -> Result<()> {
-    let ListAction { full, limit } = action;
+/// Writes `session` as a single line of JSON, flushed so that consumers can process it right away.
+fn write_json_line(session: &Session, mut out: impl Write) -> std::io::Result<()> {
+    serde_json::to_writer(&mut out, session)?;
+    writeln!(&mut out)?;
+    out.flush()
+}
 
-    let session_names = list_session_names().context("could not list sessions")?;
-    let limit = limit.min(session_names.len());
+fn show_to(
+    dir: &Path,
+    references: &[String],
+    mode: ShowMode,
+    options: &PrintOptions,
+    config: &RenderConfig,
+    strip_common_prefix: bool,
+    mut out: impl Write,
+) -> Result<()> {
+    let mut sessions = references
+        .iter()
+        .map(|reference| {
+            read_session_from_dir(dir, reference).context("could not read session data")
+        })
+        .collect::<Result<Vec<Session>>>()?;
 
-    for (index, reference) in session_names[0..limit].iter().enumerate() {
-        let session = read_session(reference).context("could not read session data")?;
-        let key = index + 1;
-        let max = (!full).then_some(5);
-        print_session_brief(session, key, max, stdout()).context("could not print output")?;
-        println!();
+    let mut omitted = 0;
+    if strip_common_prefix && sessions.len() > 1 {
+        let commands: Vec<Vec<String>> = sessions
+            .iter()
+            .map(|session| session.records.iter().map(|r| r.command.clone()).collect())
+            .collect();
+        omitted = common_prefix_len(&commands);
+        for session in sessions.iter_mut().skip(1) {
+            session.records.drain(..omitted);
+        }
     }
 
-    println!("({} / {} sessions)", limit, session_names.len());
+    let mut iter = sessions.into_iter().enumerate().peekable();
+
+    while let Some((index, session)) = iter.next() {
+        // the first session is shown in full
+        let omitted = if index == 0 { 0 } else { omitted };
+        let options = &PrintOptions {
+            strip_ansi: options.strip_ansi || !config.color,
+            omitted,
+            color: config.color,
+            ..options.clone()
+        };
+        match mode {
+            ShowMode::Normal => print_session(session, options, &mut out, stderr()),
+            ShowMode::Grouped => print_session_grouped(session, options, &mut out, stderr()),
+            ShowMode::Script(script) => {
+                print_session_script(session, script, options, &mut out, stderr())
+            }
+            ShowMode::Html => print_session_html(session, &mut out),
+            ShowMode::JsonLines => write_json_line(&session, &mut out),
+            ShowMode::Play { speed } => {
+                play_session(session, options, speed, sleep, &mut out, stderr())
+            }
+        }
+        .context("could not print output")?;
+        if iter.peek().is_some() && mode != ShowMode::JsonLines {
+            writeln!(&mut out)?;
+        }
+    }
 
     Ok(())
 }
 
-pub fn remove(action: RemoveAction) -> Result<()> {
-    let RemoveAction { all, session: reference_args } = action;
-
-    let session_names = list_session_names().context("could not list sessions")?;
-    let references: Vec<String> = match all {
-        true => session_names,
-        false => resolve_references(reference_args.iter(), &session_names)
-            .context("invalid `--session` argument")?,
+/// Reruns the `index`-th (1-based) executed command of `session` in the default environment,
+/// printing the recorded output followed by the fresh one.
+fn exec_stored_command(
+    session: &Session,
+    index: usize,
+    mut out: impl Write,
+) -> Result<CommandRecord> {
+    let executed: Vec<&CommandRecord> =
+        session.records.iter().filter(|r| r.status.is_attempted()).collect();
+    let stored = match index.checked_sub(1).and_then(|i| executed.get(i)) {
+        Some(record) => record,
+        None => bail!(
+            "command {} is out of range (session {} has {} commands)",
+            index,
+            session.name,
+            executed.len()
+        ),
     };
 
-    for reference in &references {
-        remove_session(reference).context("could not remove session")?;
-        println!("session {} removed", reference);
+    writeln!(&mut out, "$ {}", stored.command)?;
+    writeln!(&mut out, "--- recorded")?;
+    out.write_all(&stored.output)?;
+    if needs_newline(&stored.output_text()) {
+        writeln!(&mut out)?;
+    }
+    writeln!(&mut out, "--- now")?;
+    let result =
+        execute(&stored.command, Environment::default(), &ExecOptions::default(), &mut out)
+            .with_context(|| format!("could not execute command {}", stored.command))?;
+    if needs_newline(&String::from_utf8_lossy(&result.output)) {
+        writeln!(&mut out)?;
     }
 
-    Ok(())
+    let status = match result.succeeded {
+        true => CommandStatus::Succeeded,
+        false => CommandStatus::Failed,
+    };
+    let work_dir = result.new_env.work_dir().map(ToOwned::to_owned);
+    Ok(CommandRecord {
+        command: stored.command.clone(),
+        output: result.output,
+        status,
+        work_dir,
+        ..Default::default()
+    })
 }
 
-impl Cli {
-    pub fn run(self) -> Result<()> {
-        match self.action {
-            Action::Run(action) => run(action),
-            Action::Show(action) => show(action),
-            Action::List(action) => list(action),
-            Action::Remove(action) => remove(action),
-        }
-    }
+fn recent_names(session_names: &[String], n: usize) -> Vec<String> {
+    session_names.iter().take(n).cloned().collect()
 }
 
-#[cfg(test)]
-mod test {
-    use chrono::DateTime;
-
-    use crate::{CommandRecordSummary, SessionSummary};
+#[cfg(feature = "picker")]
+fn pick_from(dir: &Path, session_names: &[String]) -> Result<Option<String>> {
+    let entries = session_names
+        .iter()
+        .map(|name| {
+            read_session_from_dir(dir, name)
+                .map(|session| PickerEntry::from(&session.summary(false)))
+                .with_context(|| format!("could not read session {}", name))
+        })
+        .collect::<Result<Vec<PickerEntry>>>()?;
+    pick_session(&entries, || scan_line(DEFAULT_PROMPT), stderr()).context("could not pick session")
+}
 
-    use super::*;
+pub fn show(action: ShowAction, config: &RenderConfig) -> Result<()> {
+    let ShowAction {
+        format,
+        script,
+        with_shebang,
+        only_succeeded,
+        group_by_status,
+        strip_ansi,
+        wrap,
+        wrap_hard,
+        fold,
+        unfold_status,
+        number,
+        no_banner,
+        head,
+        tail,
+        count,
+        strip_common_prefix,
+        relative,
+        exec,
+        record,
+        menu,
+        play,
+        speed,
+        out,
+        append,
+        from_file,
+        session: reference_args,
+        ..
+    } = action;
+    let reference_args = with_references_from_file(reference_args, from_file.as_deref())?;
+    let relative_to = relative.then(Utc::now);
+    let wrap = match wrap {
+        Some(width) => {
+            let width = match width {
+                Some(width) => width as usize,
+                None => config
+                    .width
+                    .context("could not detect the terminal width; pass it as `--wrap=WIDTH`")?,
+            };
+            Some(Wrap { width, hard: wrap_hard })
+        }
+        None => None,
+    };
+    let unfolded = fold.then(|| match unfold_status.is_empty() {
+        true => vec![CommandStatus::Failed, CommandStatus::Errored],
+        false => unfold_status,
+    });
+    let options = PrintOptions {
+        strip_ansi,
+        number,
+        no_banner,
+        head,
+        tail,
+        relative_to,
+        wrap,
+        unfolded,
+        ..Default::default()
+    };
+    let mode = match (format, script, group_by_status) {
+        _ if play => ShowMode::Play { speed },
+        (ShowFormat::Html, _, _) => ShowMode::Html,
+        (ShowFormat::Jsonl, _, _) => ShowMode::JsonLines,
+        (ShowFormat::Text, true, _) => {
+            ShowMode::Script(ScriptOptions { shebang: with_shebang, only_succeeded })
+        }
+        (ShowFormat::Text, false, true) => ShowMode::Grouped,
+        (ShowFormat::Text, false, false) => ShowMode::Normal,
+    };
+
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    let session_names =
+        list_session_names_from_dir(&session_dir).context("could not list sessions")?;
+    if session_names.is_empty() {
+        bail!("no sessions recorded");
+    }
+
+    let references: Vec<String> = match reference_args.is_empty() {
+        #[cfg(feature = "picker")]
+        true if action.pick && config.tty && stderr().is_terminal() => {
+            match pick_from(&session_dir, &session_names)? {
+                Some(name) => vec![name],
+                None => return Ok(()),
+            }
+        }
+        true => recent_names(&session_names, count.unwrap_or(1) as usize),
+        false => resolve_references(&reference_args, &session_names)
+            .context("invalid `--session` argument")?,
+    };
+
+    if let Some(index) = exec {
+        let [reference] = references.as_slice() else {
+            bail!("`--exec` takes exactly one session");
+        };
+        let session = read_session_from_dir(&session_dir, reference)
+            .context("could not read session data")?;
+        let rerun = exec_stored_command(&session, index as usize, stdout())?;
+        if record {
+            let key_format = SessionKeyFormat::from_env();
+            let session = Session::new(Utc::now(), vec![rerun], &key_format);
+            write_session_to_dir(&session_dir, &session).context("could not write session data")?;
+            eprintln!("session {} recorded", session.name);
+        }
+        return Ok(());
+    }
+
+    if let Some(index) = menu {
+        let [reference] = references.as_slice() else {
+            bail!("`--menu` takes exactly one session");
+        };
+        let session = read_session_from_dir(&session_dir, reference)
+            .context("could not read session data")?;
+        let commands: Vec<&str> = session
+            .records
+            .iter()
+            .filter(|r| r.status.is_attempted())
+            .map(|r| r.command.as_str())
+            .collect();
+        let index = match index {
+            Some(index) => index as usize,
+            None if stdin().is_terminal() => {
+                render_command_menu(&commands, stderr())?;
+                eprint!("select a command: ");
+                let mut line = String::new();
+                if stdin().read_line(&mut line).context("could not read selection")? == 0 {
+                    return Ok(());
+                }
+                parse_menu_selection(&line)?
+            }
+            None => bail!("STDIN is not a terminal; pass the command number as `--menu=N`"),
+        };
+        println!("{}", select_command(&commands, index)?);
+        return Ok(());
+    }
+
+    #[cfg(feature = "clipboard")]
+    if action.copy {
+        let text = show_to_string(&session_dir, &references, mode, &options, strip_common_prefix)?;
+        copy_to_clipboard(&text, action.clipboard)?;
+        eprintln!("{} chars copied into clipboard", text.len());
+        return Ok(());
+    }
+
+    if let Some(path) = out {
+        let file = open_output_file(&path, append)?;
+        let config = RenderConfig::plain();
+        return show_to(
+            &session_dir,
+            &references,
+            mode,
+            &options,
+            &config,
+            strip_common_prefix,
+            file,
+        );
+    }
+
+    show_to(&session_dir, &references, mode, &options, config, strip_common_prefix, stdout())
+}
+
+#[cfg(feature = "clipboard")]
+fn show_to_string(
+    dir: &Path,
+    references: &[String],
+    mode: ShowMode,
+    options: &PrintOptions,
+    strip_common_prefix: bool,
+) -> Result<String> {
+    let mut buffer = Vec::new();
+    show_to(
+        dir,
+        references,
+        mode,
+        options,
+        &RenderConfig::plain(),
+        strip_common_prefix,
+        &mut buffer,
+    )?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+fn open_output_file(path: &Path, append: bool) -> Result<File> {
+    File::options()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| format!("could not open {}", path.display()))
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Matches the glob set against the first record of the session, annotation or not.
+fn first_command_matches(summary: &SessionSummary, globs: &GlobSet) -> bool {
+    summary.records.first().is_some_and(|r| globs.is_match(&r.command))
+}
+
+fn list_in(
+    dir: &Path,
+    action: ListAction,
+    config: &RenderConfig,
+    mut out: impl Write,
+) -> Result<()> {
+    let ListAction {
+        full,
+        limit,
+        no_truncate,
+        count_only,
+        relative,
+        preview,
+        exclude_command,
+        since_session,
+        columns,
+    } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let exclude = build_glob_set(&exclude_command).context("invalid `--exclude-command` glob")?;
+    // names are sorted newest first, so the newer sessions are the ones before the reference
+    let candidates = match since_session {
+        Some(reference) => {
+            let name = resolve_reference(&reference, &session_names)
+                .context("invalid `--since-session` argument")?;
+            let position = session_names.iter().position(|n| *n == name).expect("should not fail");
+            &session_names[..position]
+        }
+        None => &session_names[..],
+    };
+    if count_only {
+        let mut count = candidates.len();
+        // the sessions are read only to match their first commands
+        if !exclude.is_empty() {
+            for reference in candidates {
+                let session =
+                    read_session_from_dir(dir, reference).context("could not read session data")?;
+                if first_command_matches(&session.summary(false), &exclude) {
+                    count -= 1;
+                }
+            }
+        }
+        writeln!(&mut out, "{}", count)?;
+        return Ok(());
+    }
+    let limit = match limit {
+        0 => candidates.len(),
+        n => n.min(candidates.len()),
+    };
+    let width = config.width.filter(|_| !no_truncate);
+    let relative_to = relative.then(Utc::now);
+
+    let mut shown = 0;
+    let mut rows = Vec::new();
+    for (index, reference) in candidates.iter().enumerate() {
+        if shown == limit {
+            break;
+        }
+        let session =
+            read_session_from_dir(dir, reference).context("could not read session data")?;
+        if first_command_matches(&session.summary(false), &exclude) {
+            continue;
+        }
+        shown += 1;
+        let key = index + 1;
+        if columns {
+            rows.push((key, session.summary(false)));
+            continue;
+        }
+        let max = (!full).then_some(5);
+        print_session_brief(session, key, max, width, relative_to, preview, &mut out)
+            .context("could not print output")?;
+        writeln!(&mut out)?;
+    }
+    if columns {
+        writeln!(&mut out, "{}", format_session_table(&rows, relative_to))?;
+    }
+
+    writeln!(&mut out, "({} / {} sessions)", shown, session_names.len())?;
+
+    Ok(())
+}
+
+pub fn list(action: ListAction, config: &RenderConfig) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    list_in(&session_dir, action, config, stdout())
+}
+
+/// Appends the references listed in `path` to `references`.
+fn with_references_from_file(
+    mut references: Vec<String>,
+    path: Option<&Path>,
+) -> Result<Vec<String>> {
+    let Some(path) = path else {
+        return Ok(references);
+    };
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read references from {}", path.display()))?;
+    let listed = parse_reference_list(&content);
+    if listed.is_empty() && references.is_empty() {
+        bail!("no session references in {}", path.display());
+    }
+    references.extend(listed);
+    Ok(references)
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.root_cause()
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+}
+
+fn remove_in(dir: &Path, action: RemoveAction, mut out: impl Write) -> Result<()> {
+    let RemoveAction { all, from_file, session: reference_args } = action;
+    let reference_args = with_references_from_file(reference_args, from_file.as_deref())?;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let mut references: Vec<String> = match all {
+        true => session_names,
+        false => resolve_references(reference_args.iter(), &session_names)
+            .context("invalid `--session` argument")?,
+    };
+    let mut seen = HashSet::new();
+    references.retain(|reference| seen.insert(reference.clone()));
+
+    for reference in &references {
+        match remove_session_from_dir(dir, reference) {
+            Ok(()) => writeln!(&mut out, "session {} removed", reference)?,
+            Err(err) if is_not_found(&err) => {
+                eprintln!("warning: session {} was already removed", reference)
+            }
+            Err(err) => return Err(err).context("could not remove session"),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn remove(action: RemoveAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    remove_in(&session_dir, action, stdout())
+}
+
+fn search_in(dir: &Path, action: SearchAction, mut out: impl Write) -> Result<()> {
+    let SearchAction { context, invert, command_only, all_output, pattern } = action;
+    let query = SearchQuery { pattern, context, invert, command_only };
+    let filter = |record: &CommandRecord| query.match_record(record).is_some();
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let mut found = false;
+
+    for reference in &session_names {
+        let session =
+            read_session_from_dir(dir, reference).context("could not read session data")?;
+        let mut buffer = Vec::new();
+        let matched = match all_output {
+            true if session.records.iter().any(filter) => {
+                // the banner goes first into `buffer`, followed by the commands
+                let mut body = Vec::new();
+                let options = PrintOptions::default();
+                print_session_filtered(session, &options, Some(&filter), &mut body, &mut buffer)
+                    .context("could not print output")?;
+                buffer.extend(body);
+                true
+            }
+            true => false,
+            false => print_session_matches(session, &query, &mut buffer)
+                .context("could not print output")?,
+        };
+        if matched {
+            if found {
+                writeln!(&mut out)?;
+            }
+            out.write_all(&buffer)?;
+            found = true;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn search(action: SearchAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    search_in(&session_dir, action, stdout())
+}
+
+fn merge_in(dir: &Path, action: MergeAction, key_format: &SessionKeyFormat) -> Result<()> {
+    let MergeAction { name, newest, remove_sources, session: reference_args } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let references = resolve_references(reference_args.iter(), &session_names)
+        .context("invalid `--session` argument")?;
+    if let Some(name) = &name {
+        validate_session_name(name).context("invalid `--name` argument")?;
+        if session_names.contains(name) {
+            bail!("session {} already exists", name);
+        }
+    }
+
+    let mut sessions = references
+        .iter()
+        .map(|name| read_session_from_dir(dir, name).context("could not read session data"))
+        .collect::<Result<Vec<Session>>>()?;
+    // the spilled outputs of the sources may be removed below
+    sessions.iter_mut().for_each(Session::inline_spilled_outputs);
+    let mut merged =
+        merge_sessions(sessions, name, newest, key_format).context("could not merge sessions")?;
+    merged.make_name_unique_in(dir);
+    write_session_to_dir(dir, &merged).context("could not write session data")?;
+    eprintln!("session {} recorded", merged.name);
+
+    if remove_sources {
+        // the same session may be given more than once
+        let mut removed = HashSet::new();
+        for reference in references.iter().filter(|r| removed.insert(*r)) {
+            remove_session_from_dir(dir, reference).context("could not remove session")?;
+            eprintln!("session {} removed", reference);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn merge(action: MergeAction, key_format: &SessionKeyFormat) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    merge_in(&session_dir, action, key_format)
+}
+
+fn which_in(dir: &Path, action: WhichAction, mut out: impl Write) -> Result<()> {
+    let WhichAction { session: reference_args } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let references = resolve_references(reference_args.iter(), &session_names)
+        .context("could not resolve references")?;
+
+    for reference in &references {
+        writeln!(&mut out, "{}", session_path_in_dir(dir, reference).display())?;
+    }
+
+    Ok(())
+}
+
+pub fn which(action: WhichAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    which_in(&session_dir, action, stdout())
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct CommandSets {
+    only_left: Vec<String>,
+    only_right: Vec<String>,
+    both: Vec<String>,
+}
+
+/// Splits the distinct commands of two lists into those only in `left`, only in `right`, and
+/// in both. Each partition keeps the order in which its commands first appear.
+fn partition_command_sets(left: Vec<String>, right: Vec<String>) -> CommandSets {
+    let left_set: HashSet<&String> = left.iter().collect();
+    let right_set: HashSet<&String> = right.iter().collect();
+    let mut sets = CommandSets::default();
+    let mut seen = HashSet::new();
+    for command in &left {
+        if !seen.insert(command) {
+            continue;
+        }
+        match right_set.contains(command) {
+            true => sets.both.push(command.clone()),
+            false => sets.only_left.push(command.clone()),
+        }
+    }
+    for command in &right {
+        if seen.insert(command) && !left_set.contains(command) {
+            sets.only_right.push(command.clone());
+        }
+    }
+    sets
+}
+
+fn diff_in(dir: &Path, action: DiffAction, mut out: impl Write) -> Result<()> {
+    let DiffAction { left, right, .. } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let is_command = |r: &CommandRecordSummary| r.status != CommandStatus::Annotation;
+    let left_name =
+        resolve_reference(&left, &session_names).context("could not resolve reference")?;
+    let right_name =
+        resolve_reference(&right, &session_names).context("could not resolve reference")?;
+    let left_commands = lookup_commands_by(dir, [&left_name], &session_names, is_command)?;
+    let right_commands = lookup_commands_by(dir, [&right_name], &session_names, is_command)?;
+
+    let sets = partition_command_sets(left_commands, right_commands);
+    let partitions = [
+        (format!("Only in {}", left_name), sets.only_left),
+        (format!("Only in {}", right_name), sets.only_right),
+        ("In both".to_owned(), sets.both),
+    ];
+    for (n, (label, commands)) in partitions.iter().enumerate() {
+        if n > 0 {
+            writeln!(&mut out)?;
+        }
+        writeln!(&mut out, "{}:", label)?;
+        for command in commands {
+            writeln!(&mut out, "    $ {}", command)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn diff(action: DiffAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    diff_in(&session_dir, action, stdout())
+}
+
+fn describe_in(dir: &Path, action: DescribeAction, mut out: impl Write) -> Result<()> {
+    let DescribeAction { session: reference, text } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let name =
+        resolve_reference(&reference, &session_names).context("could not resolve reference")?;
+
+    let mut session = read_session_from_dir(dir, &name).context("could not read session data")?;
+    session.description = (!text.is_empty()).then_some(text);
+    write_session_to_dir(dir, &session).context("could not write session data")?;
+    writeln!(&mut out, "session {} updated", name)?;
+
+    Ok(())
+}
+
+pub fn describe(action: DescribeAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    describe_in(&session_dir, action, stdout())
+}
+
+fn note_in(dir: &Path, action: NoteAction, mut out: impl Write) -> Result<()> {
+    let NoteAction { session: reference, text } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let name =
+        resolve_reference(&reference, &session_names).context("could not resolve reference")?;
+
+    let mut session = read_session_from_dir(dir, &name).context("could not read session data")?;
+    session.notes.push((Utc::now(), text));
+    write_session_to_dir(dir, &session).context("could not write session data")?;
+    writeln!(&mut out, "session {} updated", name)?;
+
+    Ok(())
+}
+
+pub fn note(action: NoteAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    note_in(&session_dir, action, stdout())
+}
+
+fn rerun_in(
+    dir: &Path,
+    action: RerunAction,
+    key_format: &SessionKeyFormat,
+    editor: &str,
+    out: impl Write,
+) -> Result<String> {
+    let RerunAction { session: reference } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let name =
+        resolve_reference(&reference, &session_names).context("could not resolve reference")?;
+    let session = read_session_from_dir(dir, &name).context("could not read session data")?;
+
+    let mut buffer = Vec::new();
+    let options = PrintOptions::default();
+    print_session_script(session, Default::default(), &options, &mut buffer, std::io::sink())?;
+    let edited = edit_text(&String::from_utf8_lossy(&buffer), editor)?;
+    let commands = read_script(edited.as_bytes()).context("could not read edited commands")?;
+    if commands.is_empty() {
+        bail!("no commands left after editing; aborting");
+    }
+
+    // the rerun uses the defaults of `run`
+    let action = RunAction::try_parse_from(["run"]).expect("should not fail");
+    let source = SessionSource::Session(vec![name]);
+    let (recorded, ok) = run_commands(
+        dir,
+        commands,
+        source,
+        action,
+        key_format,
+        scan_line,
+        terminal_dimensions(),
+        out,
+    )?;
+    if !ok {
+        bail!("command exited with non-zero exit code");
+    }
+    Ok(recorded)
+}
+
+pub fn rerun(action: RerunAction, key_format: &SessionKeyFormat) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    rerun_in(&session_dir, action, key_format, &default_editor(), stdout())?;
+    Ok(())
+}
+
+fn export_in(dir: &Path, action: ExportAction) -> Result<()> {
+    let ExportAction { all, out, session: reference_args } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let names = match all {
+        true => session_names,
+        false => resolve_references(reference_args.iter(), &session_names)
+            .context("could not resolve references")?,
+    };
+
+    let file = File::create(&out).with_context(|| format!("could not create {}", out.display()))?;
+    export_archive_from_dir(dir, &names, file).context("could not export sessions")?;
+    eprintln!("{} sessions exported into {}", names.len(), out.display());
+
+    Ok(())
+}
+
+pub fn export(action: ExportAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    export_in(&session_dir, action)
+}
+
+fn import_in(
+    dir: &Path,
+    action: ImportAction,
+    key_format: &SessionKeyFormat,
+    mut out: impl Write,
+) -> Result<()> {
+    let ImportAction { archive } = action;
+
+    let file =
+        File::open(&archive).with_context(|| format!("could not open {}", archive.display()))?;
+    for name in import_archive_to_dir(dir, file, key_format).context("could not import sessions")? {
+        writeln!(&mut out, "session {} imported", name)?;
+    }
+
+    Ok(())
+}
+
+pub fn import(action: ImportAction, key_format: &SessionKeyFormat) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    import_in(&session_dir, action, key_format, stdout())
+}
+
+fn verify_in(dir: &Path, action: VerifyAction, mut out: impl Write) -> Result<()> {
+    let VerifyAction { fix } = action;
+
+    let session_names = list_session_names_from_dir(dir).context("could not list sessions")?;
+    let mut problems = 0;
+
+    for name in &session_names {
+        let session = match read_session_from_dir(dir, name) {
+            Ok(session) => session,
+            Err(err) => {
+                writeln!(&mut out, "{}: {:#}", name, err)?;
+                problems += 1;
+                continue;
+            }
+        };
+        if &session.name == name {
+            writeln!(&mut out, "{}: ok", name)?;
+            continue;
+        }
+        if let Err(err) = validate_session_name(&session.name) {
+            writeln!(&mut out, "{}: {:#}", name, err)?;
+            problems += 1;
+            continue;
+        }
+        let target = session_path_in_dir(dir, &session.name);
+        if fix && !target.exists() {
+            std::fs::rename(session_path_in_dir(dir, name), &target)
+                .with_context(|| format!("could not rename session {}", name))?;
+            writeln!(&mut out, "{}: renamed to {}", name, session.name)?;
+        } else {
+            writeln!(&mut out, "{}: file name does not match session {}", name, session.name)?;
+            problems += 1;
+        }
+    }
+
+    if problems > 0 {
+        bail!("{} of {} sessions have problems", problems, session_names.len());
+    }
+    Ok(())
+}
+
+pub fn verify(action: VerifyAction) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    verify_in(&session_dir, action, stdout())
+}
+
+impl Cli {
+    pub fn run(self) -> Result<()> {
+        init_logger(self.verbose);
+        let config = RenderConfig::detect(self.plain);
+        match self.action {
+            Action::Run(action) => run(*action, &SessionKeyFormat::from_env()),
+            Action::Show(action) => show(action, &config),
+            Action::List(action) => list(action, &config),
+            Action::Remove(action) => remove(action),
+            Action::Search(action) => search(action),
+            Action::Merge(action) => merge(action, &SessionKeyFormat::from_env()),
+            Action::Which(action) => which(action),
+            Action::Diff(action) => diff(action),
+            Action::Describe(action) => describe(action),
+            Action::Note(action) => note(action),
+            Action::Rerun(action) => rerun(action, &SessionKeyFormat::from_env()),
+            Action::Export(action) => export(action),
+            Action::Import(action) => import(action, &SessionKeyFormat::from_env()),
+            Action::Verify(action) => verify(action),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir, remove_dir_all, write};
+
+    use chrono::DateTime;
+    use indoc::indoc;
+    use rstest::rstest;
+    use tempfile::TempDir;
+
+    use crate::level_filter;
+
+    use super::*;
+
+    #[rstest]
+    #[case::bytes("123", Some(123))]
+    #[case::kilo("2K", Some(2048))]
+    #[case::mega("3M", Some(3 * 1024 * 1024))]
+    #[case::giga("1g", Some(1024 * 1024 * 1024))]
+    #[case::unknown_unit("1T", None)]
+    #[case::empty("", None)]
+    #[case::overflow("99999999999G", None)]
+    fn test_parse_size(#[case] s: &str, #[case] expected: Option<u64>) {
+        assert_eq!(parse_size(s).ok(), expected);
+    }
+
+    fn commands(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[rstest]
+    #[case::shared_two(
+        vec![commands(&["cd x", "make", "a"]), commands(&["cd x", "make", "b", "c"]), commands(&["cd x", "make"])],
+        2
+    )]
+    #[case::none(vec![commands(&["a"]), commands(&["b"])], 0)]
+    #[case::identical(vec![commands(&["a", "b"]), commands(&["a", "b"])], 2)]
+    #[case::single(vec![commands(&["a", "b"])], 2)]
+    #[case::empty(vec![], 0)]
+    fn test_common_prefix_len(#[case] lists: Vec<Vec<String>>, #[case] expected: usize) {
+        assert_eq!(common_prefix_len(&lists), expected);
+    }
+
+    #[rstest]
+    #[case::secs("90", Some(90))]
+    #[case::secs_unit("90s", Some(90))]
+    #[case::mins("2m", Some(120))]
+    #[case::hours("1h", Some(3600))]
+    #[case::unknown_unit("1d", None)]
+    #[case::empty("", None)]
+    fn test_parse_duration(#[case] s: &str, #[case] expected: Option<u64>) {
+        assert_eq!(parse_duration(s).ok().map(|d| d.as_secs()), expected);
+    }
+
+    #[rstest]
+    #[case::integer("2", Some(2.0))]
+    #[case::fraction("0.5", Some(0.5))]
+    #[case::zero("0", Some(0.0))]
+    #[case::negative("-1", None)]
+    #[case::infinite("inf", None)]
+    #[case::text("fast", None)]
+    fn test_parse_speed(#[case] s: &str, #[case] expected: Option<f64>) {
+        assert_eq!(parse_speed(s).ok(), expected);
+    }
+
+    #[test]
+    fn test_cli_verbose() {
+        let cli = Cli::try_parse_from(["scener", "list", "--verbose", "--verbose"]).unwrap();
+        assert_eq!(level_filter(cli.verbose), log::LevelFilter::Debug);
+
+        let cli = Cli::try_parse_from(["scener", "list"]).unwrap();
+        assert_eq!(level_filter(cli.verbose), log::LevelFilter::Warn);
+    }
+
+    /// Builds a session of succeeded commands without output, recorded at 2020-01-01.
+    fn test_session(name: &str, commands: &[&str]) -> Session {
+        let records = commands
+            .iter()
+            .map(|command| CommandRecord {
+                command: command.to_string(),
+                status: CommandStatus::Succeeded,
+                ..Default::default()
+            })
+            .collect();
+        let recorded_at = DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        Session { name: name.into(), recorded_at, records, ..Default::default() }
+    }
+
+    fn write_test_sessions(dir: &Path, n: usize) {
+        for i in 1..=n {
+            let session = test_session(&format!("test{}", i), &[&format!("cmd{}", i)]);
+            write_session_to_dir(dir, &session).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_merge_remove_duplicated_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+
+        let args = ["merge", "--name", "merged", "--remove-sources", "@1", "@1", "test1"];
+        merge_in(
+            temp_dir.path(),
+            MergeAction::try_parse_from(args).unwrap(),
+            &SessionKeyFormat::default(),
+        )
+        .unwrap();
+
+        assert_eq!(list_session_names_from_dir(temp_dir.path()).unwrap(), vec!["merged"]);
+        let merged = read_session_from_dir(temp_dir.path(), "merged").unwrap();
+        let commands: Vec<&str> = merged.records.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["cmd2", "cmd2", "cmd1"]);
+    }
+
+    #[test]
+    fn test_merge_invalid_name() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 1);
+
+        let args = ["merge", "--name", "../merged", "test1"];
+        assert!(merge_in(
+            temp_dir.path(),
+            MergeAction::try_parse_from(args).unwrap(),
+            &SessionKeyFormat::default()
+        )
+        .is_err());
+        assert_eq!(list_session_names_from_dir(temp_dir.path()).unwrap(), vec!["test1"]);
+    }
+
+    #[test]
+    fn test_list_unlimited() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 12);
+
+        let action = ListAction::try_parse_from(["list", "--limit", "0"]).unwrap();
+        let config = RenderConfig::plain();
+        let mut out = Vec::new();
+        list_in(temp_dir.path(), action, &config, &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().filter(|line| line.contains(": test")).count(), 12);
+        assert!(out.ends_with("(12 / 12 sessions)\n"));
+    }
+
+    #[test]
+    fn test_list_exclude_command() {
+        let temp_dir = TempDir::new().unwrap();
+        for (name, command) in [("ls", "ls -la"), ("build", "cargo build")] {
+            let session = test_session(name, &[command]);
+            write_session_to_dir(temp_dir.path(), &session).unwrap();
+        }
+
+        let action = ListAction::try_parse_from(["list", "--exclude-command", "ls*"]).unwrap();
+        let config = RenderConfig::plain();
+        let mut out = Vec::new();
+        list_in(temp_dir.path(), action, &config, &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("$ cargo build"));
+        assert!(!out.contains("$ ls -la"));
+        assert!(out.ends_with("(1 / 2 sessions)\n"));
+    }
+
+    #[rstest]
+    #[case::since(&["--since-session", "@3"], &["test5", "test4"])]
+    #[case::limited(&["--since-session", "@3", "--limit", "1"], &["test5"])]
+    #[case::newest(&["--since-session", "@1"], &[])]
+    fn test_list_since_session(#[case] args: &[&str], #[case] expected: &[&str]) {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 5);
+
+        let action = ListAction::try_parse_from([&["list"], args].concat()).unwrap();
+        let config = RenderConfig::plain();
+        let mut out = Vec::new();
+        list_in(temp_dir.path(), action, &config, &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let names: Vec<&str> = out
+            .lines()
+            .filter_map(|line| line.split_once(": ").map(|(_, rest)| rest))
+            .filter_map(|rest| rest.split_whitespace().next())
+            .collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_list_columns() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 5);
+
+        let action = ListAction::try_parse_from(["list", "--columns", "--limit", "2"]).unwrap();
+        let config = RenderConfig::plain();
+        let mut out = Vec::new();
+        list_in(temp_dir.path(), action, &config, &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("#  NAME"));
+        assert!(lines[1].starts_with("1  test5"));
+        assert!(lines[2].starts_with("2  test4"));
+        assert_eq!(lines[4], "(2 / 5 sessions)");
+    }
+
+    #[rstest]
+    #[case::all(&[], "3\n")]
+    #[case::excluded(&["--exclude-command", "cmd1"], "2\n")]
+    #[case::since(&["--since-session", "test2"], "1\n")]
+    fn test_list_count_only(#[case] filter: &[&str], #[case] expected: &str) {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 3);
+
+        let args = ["list", "--count-only"].iter().chain(filter);
+        let action = ListAction::try_parse_from(args).unwrap();
+        let config = RenderConfig::plain();
+        let mut out = Vec::new();
+        list_in(temp_dir.path(), action, &config, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_remove_twice() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+
+        let action = RemoveAction::try_parse_from(["remove", "@1", "@1", "test2"]).unwrap();
+        let mut out = Vec::new();
+        remove_in(temp_dir.path(), action, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "session test2 removed\n");
+        assert!(!temp_dir.path().join("test2.json").exists());
+        assert!(temp_dir.path().join("test1.json").exists());
+    }
+
+    #[test]
+    fn test_show_to_file() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+        let path = temp_dir.path().join("notes.md");
+        write(&path, "# Notes\n").unwrap();
+
+        let options = PrintOptions::default();
+        for (reference, append) in [("test1", false), ("test2", true)] {
+            let file = open_output_file(&path, append).unwrap();
+            let references = [reference.to_owned()];
+            show_to(
+                temp_dir.path(),
+                &references,
+                ShowMode::Normal,
+                &options,
+                &RenderConfig::plain(),
+                false,
+                file,
+            )
+            .unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "$ cmd1\n$ cmd2\n");
+    }
+
+    #[test]
+    fn test_remove_from_file() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+        let path = temp_dir.path().join("refs.txt");
+        write(&path, "# stale sessions\n\n@1\n").unwrap();
+
+        let args = ["remove".as_ref(), "--from-file".as_ref(), path.as_os_str()];
+        let action = RemoveAction::try_parse_from(args).unwrap();
+        let mut out = Vec::new();
+        remove_in(temp_dir.path(), action, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "session test2 removed\n");
+        assert!(temp_dir.path().join("test1.json").exists());
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn test_show_to_string() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+
+        let references = ["test2".to_owned(), "test1".to_owned()];
+        let options = PrintOptions::default();
+        let mode = ShowMode::Script(ScriptOptions::default());
+        let text = show_to_string(temp_dir.path(), &references, mode, &options, false).unwrap();
+        assert_eq!(text, "cmd2\n\ncmd1\n");
+    }
+
+    #[test]
+    fn test_rerun() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+
+        let action = RerunAction::try_parse_from(["rerun", "@1"]).unwrap();
+        let editor = "sed -i 's/cmd2/echo edited/'";
+        let name =
+            rerun_in(temp_dir.path(), action, &SessionKeyFormat::default(), editor, Vec::new())
+                .unwrap();
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        assert_eq!(session.records.len(), 1);
+        assert_eq!(session.records[0].command, "echo edited");
+        assert_eq!(session.records[0].output, b"edited\n");
+        let original = read_session_from_dir(temp_dir.path(), "test2").unwrap();
+        assert_eq!(original.records[0].command, "cmd2");
+    }
+
+    #[rstest]
+    #[case::editor_failed("false")]
+    #[case::emptied("sed -i d")]
+    fn test_rerun_aborted(#[case] editor: &str) {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 1);
+
+        let action = RerunAction::try_parse_from(["rerun", "@1"]).unwrap();
+        assert!(rerun_in(
+            temp_dir.path(),
+            action,
+            &SessionKeyFormat::default(),
+            editor,
+            Vec::new()
+        )
+        .is_err());
+        assert_eq!(list_session_names_from_dir(temp_dir.path()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_describe() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+
+        let action = DescribeAction::try_parse_from(["describe", "@1", "fix the build"]).unwrap();
+        let mut out = Vec::new();
+        describe_in(temp_dir.path(), action, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "session test2 updated\n");
+
+        let session = read_session_from_dir(temp_dir.path(), "test2").unwrap();
+        assert_eq!(session.description.as_deref(), Some("fix the build"));
+
+        let action = DescribeAction::try_parse_from(["describe", "test2", ""]).unwrap();
+        describe_in(temp_dir.path(), action, Vec::new()).unwrap();
+        let session = read_session_from_dir(temp_dir.path(), "test2").unwrap();
+        assert_eq!(session.description, None);
+    }
+
+    #[test]
+    fn test_note() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+
+        let action = NoteAction::try_parse_from(["note", "@1", "first"]).unwrap();
+        let mut out = Vec::new();
+        note_in(temp_dir.path(), action, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "session test2 updated\n");
+        let first = read_session_from_dir(temp_dir.path(), "test2").unwrap().notes;
+
+        let action = NoteAction::try_parse_from(["note", "test2", "second"]).unwrap();
+        note_in(temp_dir.path(), action, Vec::new()).unwrap();
+
+        let notes = read_session_from_dir(temp_dir.path(), "test2").unwrap().notes;
+        let texts: Vec<&str> = notes.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(texts, vec!["first", "second"]);
+        assert_eq!(notes[0], first[0]);
+        assert!(notes[0].0 <= notes[1].0);
+    }
+
+    #[test]
+    fn test_search_all_output() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 3);
+
+        let action = SearchAction::try_parse_from(["search", "--all-output", "cmd2"]).unwrap();
+        let mut out = Vec::new();
+        search_in(temp_dir.path(), action, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.starts_with("session ")).collect();
+        assert_eq!(lines, vec!["$ cmd2"]);
+        assert!(out.starts_with("session test2 "));
+    }
+
+    #[rstest]
+    #[case::plain(RenderConfig::detect(true), "red\n")]
+    #[case::color(RenderConfig { tty: true, width: Some(80), color: true }, "\x1b[31mred\x1b[0m\n")]
+    fn test_show_color(#[case] config: RenderConfig, #[case] expected_output: &str) {
+        let temp_dir = TempDir::new().unwrap();
+        let record = CommandRecord {
+            command: "color".into(),
+            output: "\x1b[31mred".into(),
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        };
+        let session = Session { name: "test".into(), records: vec![record], ..Default::default() };
+        write_session_to_dir(temp_dir.path(), &session).unwrap();
+
+        let options = PrintOptions { no_banner: true, ..Default::default() };
+        let mut out = Vec::new();
+        let references = ["test".to_owned()];
+        show_to(temp_dir.path(), &references, ShowMode::Normal, &options, &config, false, &mut out)
+            .unwrap();
+        // `--plain` strips the escapes whether or not STDOUT is a terminal
+        let expected = format!("$ color\n{}", expected_output);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_show_json_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 3);
+
+        let references: Vec<String> = vec!["test3".into(), "test1".into()];
+        let options = PrintOptions::default();
+        let mut out = Vec::new();
+        show_to(
+            temp_dir.path(),
+            &references,
+            ShowMode::JsonLines,
+            &options,
+            &RenderConfig::plain(),
+            false,
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let names: Vec<String> =
+            out.lines().map(|line| serde_json::from_str::<Session>(line).unwrap().name).collect();
+        assert_eq!(names, vec!["test3", "test1"]);
+    }
+
+    #[test]
+    fn test_run_spill_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let spill_dir = temp_dir.path().join("spill");
+        let large = format!("head -c {} /dev/zero | tr '\\0' a", SPILL_THRESHOLD + 1);
+        let args = ["run", "--spill-output", spill_dir.to_str().unwrap(), &large, "echo small"];
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, ok) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+        assert!(ok);
+
+        let path = session_path_in_dir(temp_dir.path(), &name);
+        let session: Session = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        let spilled = spill_dir.join(format!("{}-1.out", name));
+        assert!(session.records[0].output.is_empty());
+        assert_eq!(session.records[0].output_ref.as_deref(), Some(spilled.as_path()));
+        assert_eq!(std::fs::read(&spilled).unwrap().len(), SPILL_THRESHOLD + 1);
+        assert_eq!(session.records[1].output, b"small\n");
+        assert_eq!(session.records[1].output_ref, None);
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        assert_eq!(session.records[0].output.len(), SPILL_THRESHOLD + 1);
+
+        let options = PrintOptions::default();
+        let mut out = Vec::new();
+        show_to(
+            temp_dir.path(),
+            &[name],
+            ShowMode::Normal,
+            &options,
+            &RenderConfig::plain(),
+            false,
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("\n{}\n", "a".repeat(SPILL_THRESHOLD + 1))));
+        assert!(out.contains("$ echo small\nsmall\n"));
+    }
+
+    #[test]
+    fn test_export_import() {
+        let temp_dir = TempDir::new().unwrap();
+        let session_dir = temp_dir.path().join("sessions");
+        create_dir(&session_dir).unwrap();
+        write_test_sessions(&session_dir, 2);
+        let archive = temp_dir.path().join("backup.tar.gz");
+
+        let args = ["export".as_ref(), "--all".as_ref(), "--out".as_ref(), archive.as_os_str()];
+        export_in(&session_dir, ExportAction::try_parse_from(args).unwrap()).unwrap();
+        remove_dir_all(&session_dir).unwrap();
+        create_dir(&session_dir).unwrap();
+
+        let args = ["import".as_ref(), "--archive".as_ref(), archive.as_os_str()];
+        let mut out = Vec::new();
+        import_in(
+            &session_dir,
+            ImportAction::try_parse_from(args).unwrap(),
+            &SessionKeyFormat::default(),
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "session test2 imported\nsession test1 imported\n");
+        assert_eq!(list_session_names_from_dir(&session_dir).unwrap(), vec!["test2", "test1"]);
+    }
+
+    #[test]
+    fn test_exec_stored_command() {
+        let now = DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        let record = |command: &str| CommandRecord {
+            command: command.into(),
+            output: b"old\n".to_vec(),
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        };
+        let mut session = Session::new(
+            now,
+            vec![record("echo first"), record("echo second")],
+            &SessionKeyFormat::default(),
+        );
+        session.records.insert(0, CommandRecord::annotation("note".into()));
+        // errored commands are numbered as `show --number` does
+        session.records.insert(1, CommandRecord::errored("cd gone".into()));
+
+        let mut out = Vec::new();
+        let rerun = exec_stored_command(&session, 3, &mut out).unwrap();
+        assert_eq!(rerun.command, "echo second");
+        assert_eq!(rerun.output, b"second\n");
+        assert_eq!(rerun.status, CommandStatus::Succeeded);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "$ echo second\n--- recorded\nold\n--- now\nsecond\n"
+        );
+        assert_eq!(session.records[3].output, b"old\n");
+
+        assert!(exec_stored_command(&session, 4, Vec::new()).is_err());
+        assert!(exec_stored_command(&session, 0, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_show_count() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 3);
+        let session_names = list_session_names_from_dir(temp_dir.path()).unwrap();
+
+        let action = ShowAction::try_parse_from(["show", "--count", "2"]).unwrap();
+        let references = recent_names(&session_names, action.count.unwrap() as usize);
+        assert_eq!(references, vec!["test3", "test2"]);
+        assert_eq!(recent_names(&session_names, 5).len(), 3);
+
+        assert!(ShowAction::try_parse_from(["show", "--count", "2", "@1"]).is_err());
+        assert!(ShowAction::try_parse_from(["show", "--count", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 1);
+        write(temp_dir.path().join("broken.json"), b"{").unwrap();
+
+        let action = VerifyAction::try_parse_from(["verify"]).unwrap();
+        let mut out = Vec::new();
+        assert!(verify_in(temp_dir.path(), action, &mut out).is_err());
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "test1: ok");
+        assert!(lines[1].starts_with("broken: could not read session data"));
+    }
+
+    #[test]
+    fn test_verify_fix() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 1);
+        let renamed = session_path_in_dir(temp_dir.path(), "other");
+        std::fs::rename(session_path_in_dir(temp_dir.path(), "test1"), renamed).unwrap();
+
+        let action = VerifyAction::try_parse_from(["verify"]).unwrap();
+        assert!(verify_in(temp_dir.path(), action, Vec::new()).is_err());
+
+        let action = VerifyAction::try_parse_from(["verify", "--fix"]).unwrap();
+        let mut out = Vec::new();
+        verify_in(temp_dir.path(), action, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "other: renamed to test1\n");
+        assert_eq!(list_session_names_from_dir(temp_dir.path()).unwrap(), vec!["test1"]);
+    }
+
+    #[test]
+    fn test_verify_fix_invalid_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("sessions");
+        let session = test_session("../escaped", &[]);
+        std::fs::create_dir_all(&dir).unwrap();
+        write(session_path_in_dir(&dir, "other"), serde_json::to_vec(&session).unwrap()).unwrap();
+
+        let action = VerifyAction::try_parse_from(["verify", "--fix"]).unwrap();
+        let mut out = Vec::new();
+        assert!(verify_in(&dir, action, &mut out).is_err());
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "other: invalid session name \"../escaped\"\n");
+        assert!(!temp_dir.path().join("escaped.json").exists());
+        assert!(session_path_in_dir(&dir, "other").exists());
+    }
+
+    #[test]
+    fn test_which() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+
+        let action = WhichAction::try_parse_from(["which", "@1", "test1"]).unwrap();
+        let mut out = Vec::new();
+        which_in(temp_dir.path(), action, &mut out).unwrap();
+
+        let expected = format!(
+            "{}\n{}\n",
+            temp_dir.path().join("test2.json").display(),
+            temp_dir.path().join("test1.json").display(),
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        let action = WhichAction::try_parse_from(["which", "@3"]).unwrap();
+        assert!(which_in(temp_dir.path(), action, Vec::new()).is_err());
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[rstest]
+    #[case::overlapping(
+        &["a", "b", "c", "b"],
+        &["d", "c", "a", "e"],
+        &["b"],
+        &["d", "e"],
+        &["a", "c"],
+    )]
+    #[case::disjoint(&["a", "b"], &["c", "d"], &["a", "b"], &["c", "d"], &[])]
+    #[case::same(&["b", "a"], &["a", "b", "a"], &[], &[], &["b", "a"])]
+    fn test_partition_command_sets(
+        #[case] left: &[&str],
+        #[case] right: &[&str],
+        #[case] only_left: &[&str],
+        #[case] only_right: &[&str],
+        #[case] both: &[&str],
+    ) {
+        let expected = CommandSets {
+            only_left: strings(only_left),
+            only_right: strings(only_right),
+            both: strings(both),
+        };
+        assert_eq!(partition_command_sets(strings(left), strings(right)), expected);
+    }
+
+    #[test]
+    fn test_diff_set() {
+        let temp_dir = TempDir::new().unwrap();
+        for (name, commands) in [("old", &["make", "make test"]), ("new", &["make lint", "make"])] {
+            let session = test_session(name, commands);
+            write_session_to_dir(temp_dir.path(), &session).unwrap();
+        }
+
+        let action = DiffAction::try_parse_from(["diff", "--set", "old", "new"]).unwrap();
+        let mut out = Vec::new();
+        diff_in(temp_dir.path(), action, &mut out).unwrap();
+
+        let expected = indoc! {"
+            Only in old:
+                $ make test
+
+            Only in new:
+                $ make lint
+
+            In both:
+                $ make
+        "};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert!(DiffAction::try_parse_from(["diff", "old", "new"]).is_err());
+    }
+
+    #[test]
+    fn test_initial_environment_invalid() {
+        assert!(initial_environment(None, None, &["NOVALUE".into()], false).is_err());
+    }
+
+    #[test]
+    fn test_env_overrides_env_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(".env");
+        write(&path, b"# comment\nA=\"from file\"\nB=from file\n").unwrap();
+
+        let actual = env_overrides(Some(&path), &["B=explicit".into()]).unwrap();
+        let expected: Vec<(String, String)> =
+            vec![("A".into(), "from file".into()), ("B".into(), "explicit".into())];
+        assert_eq!(actual, expected);
+
+        assert!(env_overrides(Some(&temp_dir.path().join("missing")), &[]).is_err());
+    }
 
     #[test]
     fn test_collect_commands() {
@@ -315,10 +2669,12 @@ mod test {
                     CommandRecordSummary {
                         command: "cmd1a".into(),
                         status: CommandStatus::Succeeded,
+                        output_preview: None,
                     },
                     CommandRecordSummary {
                         command: "cmd1b".into(),
                         status: CommandStatus::Succeeded,
+                        output_preview: None,
                     },
                 ],
             },
@@ -329,23 +2685,463 @@ mod test {
                     CommandRecordSummary {
                         command: "cmd2a".into(),
                         status: CommandStatus::Succeeded,
+                        output_preview: None,
                     },
                     CommandRecordSummary {
                         command: "cmd2b".into(),
                         status: CommandStatus::Succeeded,
+                        output_preview: None,
                     },
                     CommandRecordSummary {
                         command: "cmd2c".into(),
                         status: CommandStatus::Succeeded,
+                        output_preview: None,
                     },
                 ],
             },
         ];
-        let actual = collect_commands(&sessions);
+        let actual = collect_commands_by(&sessions, |_| true);
         let expected: Vec<String> = vec!["cmd1a", "cmd1b", "cmd2a", "cmd2b", "cmd2c"]
             .into_iter()
             .map(ToOwned::to_owned)
             .collect();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_collect_commands_failed_only() {
+        let now = DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        let sessions = vec![SessionSummary {
+            name: "test1".into(),
+            recorded_at: now,
+            records: vec![
+                CommandRecordSummary {
+                    command: "cmd1a".into(),
+                    status: CommandStatus::Failed,
+                    output_preview: None,
+                },
+                CommandRecordSummary {
+                    command: "cmd1b".into(),
+                    status: CommandStatus::Succeeded,
+                    output_preview: None,
+                },
+                CommandRecordSummary {
+                    command: "cmd1c".into(),
+                    status: CommandStatus::Errored,
+                    output_preview: None,
+                },
+            ],
+        }];
+        let failed = |r: &CommandRecordSummary| r.status.is_failed_or_errored();
+        let actual = collect_commands_by(&sessions, failed);
+        assert_eq!(actual, vec!["cmd1a".to_owned(), "cmd1c".to_owned()]);
+    }
+
+    #[test]
+    fn test_lookup_commands_duplicated() {
+        let temp_dir = TempDir::new().unwrap();
+        write_test_sessions(temp_dir.path(), 2);
+        let session_names = list_session_names_from_dir(temp_dir.path()).unwrap();
+
+        let references = ["@1", "@2", "test2", "@1"];
+        let actual = lookup_commands(temp_dir.path(), references, &session_names).unwrap();
+        assert_eq!(actual, vec!["cmd2", "cmd1"]);
+    }
+
+    #[test]
+    fn test_run_command_and_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("script");
+        write(&path, b"echo $X\n").unwrap();
+
+        let path_arg = path.to_str().unwrap();
+        let args = ["run", "-f", path_arg, "export X=header", "--also", "echo last"];
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, ok) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+        assert!(ok);
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        let commands: Vec<&str> = session.records.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["export X=header", "echo $X", "echo last"]);
+        assert_eq!(session.records[1].output, b"header\n");
+        assert_eq!(session.source, Some(SessionSource::File(vec![path.clone()])));
+
+        assert!(RunAction::try_parse_from(["run", "-f", path_arg, "-s", "@1"]).is_err());
+    }
+
+    #[test]
+    fn test_read_commands_with_also() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("script");
+        write(&path, b"abc\ndef\n").unwrap();
+
+        let action = RunAction::try_parse_from([
+            "run".as_ref(),
+            "--also".as_ref(),
+            "ghi".as_ref(),
+            "--file".as_ref(),
+            path.as_os_str(),
+            "--also".as_ref(),
+            "jkl".as_ref(),
+        ])
+        .unwrap();
+        let (actual, source) = read_commands(temp_dir.path(), &action).unwrap();
+        let expected: Vec<String> =
+            vec!["abc", "def", "ghi", "jkl"].into_iter().map(ToOwned::to_owned).collect();
+        assert_eq!(expected, actual);
+        assert_eq!(source, SessionSource::File(vec![path]));
+    }
+
+    #[rstest]
+    #[case::fail_fast(&[], false, &[CommandStatus::Failed, CommandStatus::Skipped])]
+    #[case::keep_going(&["--keep-going"], false, &[CommandStatus::Failed, CommandStatus::Succeeded])]
+    #[case::unchecked(&["--unchecked"], false, &[CommandStatus::Failed, CommandStatus::Succeeded])]
+    #[case::succeeded(&["-k"], true, &[CommandStatus::Succeeded, CommandStatus::Succeeded])]
+    fn test_run_keep_going(
+        #[case] flags: &[&str],
+        #[case] first_ok: bool,
+        #[case] expected: &[CommandStatus],
+    ) {
+        let temp_dir = TempDir::new().unwrap();
+        let first = if first_ok { "true" } else { "false" };
+        let args = [&["run"], flags, &[first, "echo after"]].concat();
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, ok) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        let statuses: Vec<CommandStatus> = session.records.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, expected);
+        assert_eq!(ok, first_ok || !flags.is_empty());
+    }
+
+    #[rstest]
+    #[case::forwarded(&[], b"X=1\n")]
+    #[case::discarded(&["--no-env-forward-on-failure"], b"X=\n")]
+    fn test_run_env_forward_on_failure(#[case] flags: &[&str], #[case] expected: &[u8]) {
+        let temp_dir = TempDir::new().unwrap();
+        let args = [&["run", "-k"], flags, &["export X=1; false", "echo X=$X"]].concat();
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, _) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        assert_eq!(session.records[0].status, CommandStatus::Failed);
+        assert_eq!(session.records[1].output, expected);
+    }
+
+    #[test]
+    fn test_run_strip_prompts() {
+        let temp_dir = TempDir::new().unwrap();
+        let args = ["run", "--strip-prompts", "$ export X=1", "$ echo $X", "$X"];
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, _) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        let commands: Vec<&str> = session.records.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["export X=1", "echo $X", "$X"]);
+        assert_eq!(session.records[1].output, b"1\n");
+    }
+
+    #[rstest]
+    #[case::hashed(&["--hash-output"], true)]
+    #[case::default(&[], false)]
+    fn test_run_hash_output(#[case] flags: &[&str], #[case] hashed: bool) {
+        let temp_dir = TempDir::new().unwrap();
+        let args = [&["run"], flags, &["echo same", "echo same", "echo other"]].concat();
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, _) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        let hashes: Vec<Option<&str>> =
+            session.records.iter().map(|r| r.output_hash.as_deref()).collect();
+        assert_eq!(hashes[0].is_some(), hashed);
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(hashes[0] == hashes[2], !hashed);
+    }
+
+    #[test]
+    fn test_run_join() {
+        let temp_dir = TempDir::new().unwrap();
+        let args = ["run", "@join", "X=joined", "echo $X", "@end", "echo $X"];
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, ok) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+        assert!(ok);
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        let commands: Vec<&str> = session.records.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["X=joined\necho $X", "echo $X"]);
+        assert_eq!(session.records[0].output, b"joined\n");
+        assert_eq!(session.records[1].output, b"\n");
+    }
+
+    #[rstest]
+    #[case::stop_on_failure(&[])]
+    #[case::keep_going(&["--keep-going"])]
+    fn test_run_interactive_errored(#[case] args: &[&str]) {
+        let temp_dir = TempDir::new().unwrap();
+        let cwd = temp_dir.path().to_str().unwrap();
+        let action =
+            RunAction::try_parse_from([&["run", "-i", "--cwd", cwd], args].concat()).unwrap();
+        // bash cannot start in a removed working directory; an errored command never stops the
+        // interactive loop, whether or not `--keep-going` is given
+        let lines = ["echo before", "mkdir gone && cd gone && rmdir ../gone", "pwd", "echo after"];
+        let mut lines = lines.into_iter();
+        let read_line = |_: &str| Ok(lines.next().map(ToOwned::to_owned));
+        let (name, ok) = run_commands(
+            temp_dir.path(),
+            Vec::new(),
+            SessionSource::Interactive,
+            action,
+            &SessionKeyFormat::default(),
+            read_line,
+            Some((120, 40)),
+            Vec::new(),
+        )
+        .unwrap();
+        assert!(ok);
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        assert_eq!(session.tty_size, Some((120, 40)));
+        assert_eq!(session.work_dir.as_deref(), Some(cwd));
+        let statuses: Vec<CommandStatus> = session.records.iter().map(|r| r.status).collect();
+        let expected = [
+            CommandStatus::Succeeded,
+            CommandStatus::Succeeded,
+            CommandStatus::Errored,
+            CommandStatus::Errored,
+        ];
+        assert_eq!(statuses, expected);
+        assert_eq!(session.records[0].output, b"before\n");
+    }
+
+    #[rstest]
+    #[case::last_only(false, &[CommandStatus::Succeeded])]
+    #[case::all(true, &[CommandStatus::Failed, CommandStatus::Failed, CommandStatus::Succeeded])]
+    fn test_run_command_with_retry(#[case] record_all: bool, #[case] expected: &[CommandStatus]) {
+        let temp_dir = TempDir::new().unwrap();
+        let counter = temp_dir.path().join("counter");
+        // fails on the first two attempts
+        let command = format!(
+            "n=$(($(cat {0} 2>/dev/null || echo 0) + 1)); echo $n > {0}; echo $n; [ $n -ge 3 ]",
+            counter.display()
+        );
+        let retry = RetryPolicy { count: 3, delay: Duration::from_secs(2), record_all };
+        let mut sleeps = Vec::new();
+        let (_, records, ok) = run_command_with_retry(
+            Environment::default(),
+            command,
+            &Default::default(),
+            &Default::default(),
+            &retry,
+            |d| sleeps.push(d),
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+
+        assert!(ok);
+        assert_eq!(sleeps, vec![Duration::from_secs(2); 2]);
+        let statuses: Vec<CommandStatus> = records.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, expected);
+        assert_eq!(records.last().unwrap().output, b"3\n");
+    }
+
+    #[test]
+    fn test_run_command_with_retry_exhausted() {
+        let retry = RetryPolicy { count: 2, ..Default::default() };
+        let mut attempts = 0;
+        let (_, records, ok) = run_command_with_retry(
+            Environment::default(),
+            "false".into(),
+            &Default::default(),
+            &Default::default(),
+            &retry,
+            |_| attempts += 1,
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+
+        assert!(!ok);
+        assert_eq!(attempts, 2);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].status, CommandStatus::Failed);
+    }
+
+    #[test]
+    fn test_run_retry_keep_going() {
+        assert!(RunAction::try_parse_from(["run", "--retry", "1", "-k", "true"]).is_err());
+    }
+
+    #[test]
+    fn test_run_parallel() {
+        let env = Environment::default();
+        let commands: Vec<String> = vec!["sleep 0.5 && echo first", "export ABC=1 && echo second"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+
+        let (new_env, records, ok) = run_parallel(
+            env.clone(),
+            commands.clone(),
+            2,
+            &Default::default(),
+            &Default::default(),
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+
+        assert!(ok);
+        assert_eq!(new_env, env);
+        assert_eq!(records.iter().map(|r| r.command.clone()).collect::<Vec<_>>(), commands);
+        assert_eq!(records[0].output, b"first\n");
+        assert_eq!(records[1].output, b"second\n");
+    }
+
+    #[test]
+    fn test_run_parallel_work_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let work_dir = temp_dir.path().to_str().unwrap().to_owned();
+        let env = Environment::initial(Some(work_dir.clone()), Vec::new(), false, Vec::new());
+        let commands = vec!["cd / && pwd".to_owned(), "pwd".to_owned()];
+
+        let (_, records, ok) = run_parallel(
+            env,
+            commands,
+            2,
+            &Default::default(),
+            &Default::default(),
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+
+        assert!(ok);
+        assert_eq!(records[0].output, b"/\n");
+        for record in &records {
+            assert_eq!(record.work_dir.as_deref(), Some(work_dir.as_str()));
+        }
+    }
+
+    #[test]
+    fn test_run_deadline() {
+        let deadline = Some(Instant::now() + Duration::from_millis(300));
+        let options = ExecOptions { deadline, ..Default::default() };
+        let env = Environment::default();
+
+        let started = Instant::now();
+        let command = "echo start && sleep 5 && echo end".into();
+        let (new_env, record, ok) =
+            run_command(env.clone(), command, &Default::default(), &options, Vec::new(), None)
+                .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(!ok);
+        assert_eq!(new_env, env);
+        assert_eq!(record.status, CommandStatus::Failed);
+        assert_eq!(record.output, b"start\n");
+
+        let remaining = vec![ScriptItem::Command("echo next".into())];
+        let skipped = skipped_records(remaining.into_iter());
+        assert_eq!(skipped[0].status, CommandStatus::Skipped);
+    }
+
+    #[test]
+    fn test_run_strict_utf8() {
+        let temp_dir = TempDir::new().unwrap();
+        let args = ["run", "--strict-utf8", "-u", "echo ok", r"printf 'bad\xff'", "echo after"];
+        let action = RunAction::try_parse_from(args).unwrap();
+        let (name, ok) =
+            run_once(temp_dir.path(), action, &SessionKeyFormat::default(), Vec::new()).unwrap();
+        assert!(!ok);
+
+        let session = read_session_from_dir(temp_dir.path(), &name).unwrap();
+        let statuses: Vec<CommandStatus> = session.records.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![CommandStatus::Succeeded, CommandStatus::Failed, CommandStatus::Skipped]
+        );
+        assert_eq!(session.records[1].output, b"bad\xff");
+    }
+
+    #[test]
+    fn test_run_command_wrapped() {
+        let wrapper =
+            CommandWrapper { prefix: "echo before; ".into(), suffix: "; echo after".into() };
+        let options = ExecOptions::default();
+        let (_, record, ok) = run_command(
+            Environment::default(),
+            "echo hi".into(),
+            &wrapper,
+            &options,
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+
+        assert!(ok);
+        assert_eq!(record.command, "echo hi");
+        assert_eq!(record.output, b"before\nhi\nafter\n");
+    }
+
+    #[test]
+    fn test_run_command_live_output() {
+        let options = ExecOptions::default();
+        let command = "echo first && echo -n second".into();
+        let mut out = Vec::new();
+        let (_, record, _) = run_command(
+            Environment::default(),
+            command,
+            &Default::default(),
+            &options,
+            &mut out,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(record.output, b"first\nsecond");
+        assert_eq!(out, b"$ echo first && echo -n second\nfirst\nsecond\n");
+    }
+
+    #[test]
+    fn test_run_prompt_stderr() {
+        let options = ExecOptions::default();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let command = "echo hi".into();
+        let prompt_out = Some(&mut err as &mut dyn Write);
+        run_command(
+            Environment::default(),
+            command,
+            &Default::default(),
+            &options,
+            &mut out,
+            prompt_out,
+        )
+        .unwrap();
+        assert_eq!(out, b"hi\n");
+        assert_eq!(err, b"$ echo hi\n");
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let commands = vec!["echo a".into(), "echo b".into()];
+        let prompt_out = Some(&mut err as &mut dyn Write);
+        run_parallel(
+            Environment::default(),
+            commands,
+            2,
+            &Default::default(),
+            &options,
+            &mut out,
+            prompt_out,
+        )
+        .unwrap();
+        assert_eq!(out, b"a\nb\n");
+        assert_eq!(err, b"$ echo a\n\n$ echo b\n");
+    }
 }
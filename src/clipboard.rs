@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use duct::cmd;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ClipboardBackend {
+    /// Built-in clipboard access; on X11 the text may be gone once scener exits
+    Arboard,
+    /// Built-in clipboard access, waiting until another program takes over the clipboard
+    ArboardWait,
+    /// Pipe the text into `wl-copy` (Wayland)
+    WlCopy,
+    /// Pipe the text into `xclip -selection clipboard` (X11)
+    Xclip,
+}
+
+// on these platforms the clipboard is served by the process that set it
+const SERVES_OWN_CLIPBOARD: bool =
+    cfg!(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))));
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+fn set_text_and_wait(clipboard: &mut arboard::Clipboard, text: &str) -> Result<(), arboard::Error> {
+    use arboard::SetExtLinux;
+    clipboard.set().wait().text(text)
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn set_text_and_wait(clipboard: &mut arboard::Clipboard, text: &str) -> Result<(), arboard::Error> {
+    clipboard.set_text(text)
+}
+
+pub fn copy_to_clipboard(text: &str, backend: ClipboardBackend) -> Result<()> {
+    match backend {
+        ClipboardBackend::Arboard => {
+            arboard::Clipboard::new()
+                .and_then(|mut cb| cb.set_text(text))
+                .context("could not set text to clipboard")?;
+            if SERVES_OWN_CLIPBOARD {
+                eprintln!(
+                    "warning: the clipboard may be cleared when scener exits; \
+                     use `--clipboard arboard-wait`, `wl-copy` or `xclip` to keep it"
+                );
+            }
+        }
+        ClipboardBackend::ArboardWait => {
+            if SERVES_OWN_CLIPBOARD {
+                eprintln!("waiting until another program takes over the clipboard");
+            }
+            arboard::Clipboard::new()
+                .and_then(|mut cb| set_text_and_wait(&mut cb, text))
+                .context("could not set text to clipboard")?;
+        }
+        ClipboardBackend::WlCopy => {
+            cmd!("wl-copy").stdin_bytes(text).run().context("could not run `wl-copy`")?;
+        }
+        ClipboardBackend::Xclip => {
+            cmd!("xclip", "-selection", "clipboard")
+                .stdin_bytes(text)
+                .run()
+                .context("could not run `xclip`")?;
+        }
+    }
+    Ok(())
+}
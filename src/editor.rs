@@ -0,0 +1,49 @@
+use std::fs::{read_to_string, write};
+
+use anyhow::{bail, Context, Result};
+use duct::cmd;
+use tempfile::TempDir;
+
+/// Returns the editor command from `$VISUAL` or `$EDITOR`, falling back to `vi`.
+pub fn default_editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_owned())
+}
+
+/// Lets the user edit `content` with `editor` and returns the saved text. The editor command
+/// is run by `bash`, so it may contain arguments (e.g. `code --wait`).
+pub fn edit_text(content: &str, editor: &str) -> Result<String> {
+    let temp_dir = TempDir::new().context("could not create temporary directory")?;
+    let path = temp_dir.path().join("scener-edit.sh");
+    write(&path, content).context("could not write editor buffer")?;
+
+    let status = cmd!("bash", "-c", format!(r#"{} "$1""#, editor), "bash", &path)
+        .unchecked()
+        .run()
+        .with_context(|| format!("could not run editor `{}`", editor))?
+        .status;
+    if !status.success() {
+        bail!("editor exited with non-zero exit code");
+    }
+
+    read_to_string(&path).context("could not read editor buffer")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_edit_text() {
+        let edited = edit_text("echo one\necho two\n", "sed -i 's/two/three/'").unwrap();
+        assert_eq!(edited, "echo one\necho three\n");
+    }
+
+    #[test]
+    fn test_edit_text_failed() {
+        assert!(edit_text("echo one\n", "false").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{bail, Result};
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+fn decode_base64_char(c: u8) -> Result<u32> {
+    let value = match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => bail!("invalid base64 character: {:?}", c as char),
+    };
+    Ok(value as u32)
+}
+
+pub fn decode_base64(s: &str) -> Result<Vec<u8>> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(4) {
+        bail!("invalid base64 length: {}", s.len());
+    }
+
+    let mut decoded = Vec::with_capacity(s.len() / 4 * 3);
+    for (index, chunk) in s.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && index + 1 != s.len() / 4) {
+            bail!("invalid base64 padding");
+        }
+        let mut n = 0;
+        for &c in &chunk[..4 - padding] {
+            n = n << 6 | decode_base64_char(c)?;
+        }
+        n <<= 6 * padding;
+        decoded.extend(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][..3 - padding]);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::empty(b"", "")]
+    #[case::one(b"f", "Zg==")]
+    #[case::two(b"fo", "Zm8=")]
+    #[case::three(b"foo", "Zm9v")]
+    #[case::six(b"foobar", "Zm9vYmFy")]
+    #[case::binary(b"\x00\xff\xfe", "AP/+")]
+    fn test_base64(#[case] bytes: &[u8], #[case] encoded: &str) {
+        assert_eq!(encode_base64(bytes), encoded);
+        assert_eq!(decode_base64(encoded).ok().as_deref(), Some(bytes));
+    }
+
+    #[rstest]
+    #[case::length("Zm9")]
+    #[case::char("Zm9*")]
+    #[case::inner_padding("Zg==Zm9v")]
+    #[case::excess_padding("Z===")]
+    fn test_decode_base64_invalid(#[case] s: &str) {
+        assert!(decode_base64(s).is_err());
+    }
+}
@@ -1,37 +1,52 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read, Write};
 use std::iter::Iterator;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
 
 use anyhow::{bail, Context, Result};
 use duct::cmd;
 use tempfile::TempDir;
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Environment {
     env_vars: Option<Vec<(String, String)>>,
     work_dir: Option<String>,
 }
 
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExecOptions {
+    pub mem_limit: Option<u64>,
+    pub cpu_limit: Option<u64>,
+    pub deadline: Option<Instant>,
+    pub strict_utf8: bool,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct CommandResult {
     pub new_env: Environment,
-    pub output: String,
+    pub output: Vec<u8>,
     pub succeeded: bool,
+    /// Byte offset of the first invalid UTF-8 sequence in the output, under `strict_utf8`
+    pub invalid_utf8_at: Option<usize>,
 }
 
-pub fn parse_env_file<B: BufRead>(content: &mut B) -> Result<Environment> {
+// variables bash updates on every invocation; carrying them forward makes the env drift
+const NOISY_ENV_VARS: &[&str] = &["SHLVL", "_"];
+
+// variables kept from the parent process under `--clean-env`
+const CLEAN_ENV_VARS: &[&str] =
+    &["PATH", "HOME", "USER", "LOGNAME", "SHELL", "TERM", "LANG", "LC_ALL", "TZ", "TMPDIR"];
+
+fn parse_null_separated(content: &[u8]) -> Result<Vec<(String, String)>> {
     let mut env_vars = Vec::new();
 
-    loop {
-        let mut buf = Vec::new();
-        let n = content.read_until(b'\0', &mut buf).context("could not read env file")?;
-        if n == 0 {
-            break;
-        }
-        if buf.last().copied() == Some(b'\0') {
-            buf.pop();
-        }
-        let text = String::from_utf8(buf).context("could not parse env file as utf8")?;
+    for entry in content.split(|&b| b == b'\0').filter(|e| !e.is_empty()) {
+        let text = std::str::from_utf8(entry).context("could not parse env file as utf8")?;
         let mut parts = text.splitn(2, '=');
         let (name, value) = match (parts.next(), parts.next()) {
             (Some(name), Some(value)) => (name, value),
@@ -40,35 +55,321 @@ pub fn parse_env_file<B: BufRead>(content: &mut B) -> Result<Environment> {
         env_vars.push((name.to_owned(), value.to_owned()));
     }
 
+    Ok(env_vars)
+}
+
+const DECLARE_PREFIX: &str = "declare -x ";
+
+/// Parses the output of bash's `export -p`, e.g. `declare -x NAME="value"`. Values are
+/// double-quoted with `"`, `\`, `$` and `` ` `` escaped by a backslash, and may span lines.
+/// Exported but unset variables (`declare -x NAME`) are skipped.
+pub fn parse_export_output(content: &str) -> Result<Vec<(String, String)>> {
+    let mut env_vars = Vec::new();
+    let mut rest = content;
+
+    loop {
+        rest = rest.trim_start_matches('\n');
+        if rest.is_empty() {
+            break;
+        }
+        let Some(declared) = rest.strip_prefix(DECLARE_PREFIX) else {
+            bail!("unexpected export output format");
+        };
+
+        let name_end = declared.find(['=', '\n']).unwrap_or(declared.len());
+        let name = &declared[..name_end];
+        let Some(quoted) = declared[name_end..].strip_prefix("=\"") else {
+            rest = &declared[name_end..];
+            continue;
+        };
+
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        let mut closed_at = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    closed_at = Some(i);
+                    break;
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\' | '$' | '`'))) => value.push(c),
+                    Some((_, c)) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => value.push('\\'),
+                },
+                _ => value.push(c),
+            }
+        }
+        let Some(closed_at) = closed_at else {
+            bail!("unterminated value of variable {}", name);
+        };
+
+        env_vars.push((name.to_owned(), value));
+        rest = &quoted[closed_at + 1..];
+    }
+
+    Ok(env_vars)
+}
+
+/// Parses a dotenv-style file: `KEY=VALUE` per line with an optional `export ` prefix, blank
+/// lines and `#` comments. Double-quoted values understand `\n`, `\"` and `\\` escapes,
+/// single-quoted values are taken literally, and unquoted values end at ` #`.
+pub fn parse_dotenv(content: &str) -> Result<Vec<(String, String)>> {
+    let mut env_vars = Vec::new();
+
+    for (number, line) in content.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {}: expected KEY=VALUE", number);
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            bail!("line {}: invalid variable name `{}`", number, key);
+        }
+
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            let mut unquoted = String::new();
+            let mut chars = quoted.chars();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some('n') => unquoted.push('\n'),
+                        Some(c @ ('"' | '\\')) => unquoted.push(c),
+                        Some(c) => {
+                            unquoted.push('\\');
+                            unquoted.push(c);
+                        }
+                        None => unquoted.push('\\'),
+                    },
+                    _ => unquoted.push(c),
+                }
+            }
+            if !closed {
+                bail!("line {}: unterminated value of variable {}", number, key);
+            }
+            unquoted
+        } else if let Some(quoted) = value.strip_prefix('\'') {
+            match quoted.split_once('\'') {
+                Some((unquoted, _)) => unquoted.to_owned(),
+                None => bail!("line {}: unterminated value of variable {}", number, key),
+            }
+        } else {
+            value.split_once(" #").map_or(value, |(v, _)| v).trim_end().to_owned()
+        };
+
+        env_vars.push((key.to_owned(), value));
+    }
+
+    Ok(env_vars)
+}
+
+/// Parses a captured environment, either NUL-separated `env -0` output or `export -p` output.
+pub fn parse_env_file<B: BufRead>(content: &mut B) -> Result<Environment> {
+    let mut buf = Vec::new();
+    content.read_to_end(&mut buf).context("could not read env file")?;
+
+    let mut env_vars = match buf.starts_with(DECLARE_PREFIX.as_bytes()) {
+        true => {
+            let text = std::str::from_utf8(&buf).context("could not parse env file as utf8")?;
+            parse_export_output(text)?
+        }
+        false => parse_null_separated(&buf)?,
+    };
+    env_vars.retain(|(k, _)| !NOISY_ENV_VARS.contains(&k.as_str()));
+
     let work_dir = env_vars.iter().find(|(k, _)| k == "PWD").map(|(_, v)| v.clone());
 
     Ok(Environment { env_vars: Some(env_vars), work_dir })
 }
 
-pub fn execute(cmd: &str, env: Environment, mut out: impl Write) -> Result<CommandResult> {
-    let temp_dir = TempDir::new().context("could not create temporary directory")?;
-    let env_path = temp_dir.path().join("env");
+impl Environment {
+    pub fn work_dir(&self) -> Option<&str> {
+        self.work_dir.as_deref()
+    }
 
-    let cmd = format!(r#"trap "env -0 > $(printf %q "$1")" EXIT; {}"#, cmd);
-    let mut prog = cmd!("bash", "-c", cmd, "bash", env_path.as_os_str())
-        .stdin_null()
-        .stderr_to_stdout()
-        .unchecked();
+    /// Builds the environment of the first command from `vars`, the environment of the parent
+    /// process. With `clean`, only the variables in `CLEAN_ENV_VARS` are taken from it instead of
+    /// all of them.
+    pub fn initial(
+        work_dir: Option<String>,
+        overrides: Vec<(String, String)>,
+        clean: bool,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        let env_vars = (clean || !overrides.is_empty()).then(|| {
+            let mut env_vars: Vec<(String, String)> = vars
+                .into_iter()
+                .filter(|(k, _)| !clean || CLEAN_ENV_VARS.contains(&k.as_str()))
+                .filter(|(k, _)| overrides.iter().all(|(o, _)| o != k))
+                .collect();
+            env_vars.extend(overrides);
+            env_vars
+        });
+        Environment { env_vars, work_dir }
+    }
+}
 
-    if let Some(work_dir) = env.work_dir {
-        prog = prog.dir(work_dir);
+impl ExecOptions {
+    pub fn is_limited(&self) -> bool {
+        self.mem_limit.is_some() || self.cpu_limit.is_some()
     }
-    if let Some(env_vars) = env.env_vars {
-        prog = prog.full_env(env_vars);
+}
+
+fn read_env_file(path: &Path) -> Result<Environment> {
+    let env_file = File::open(path).context("could not open env file")?;
+    parse_env_file(&mut BufReader::new(env_file)).context("could not parse `env` output")
+}
+
+fn keeps_env_file() -> bool {
+    std::env::var_os("SCENER_KEEP_ENV_FILE").is_some_and(|v| v == "1")
+}
+
+#[cfg(unix)]
+fn set_resource_limits(prog: duct::Expression, options: &ExecOptions) -> Result<duct::Expression> {
+    use rlimit::Resource;
+    use std::os::unix::process::CommandExt;
+
+    let ExecOptions { mem_limit, cpu_limit, .. } = options.clone();
+
+    Ok(prog.before_spawn(move |cmd| {
+        // SAFETY: the hook only calls `setrlimit`, which is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(bytes) = mem_limit {
+                    Resource::AS.set(bytes, bytes)?;
+                }
+                if let Some(secs) = cpu_limit {
+                    Resource::CPU.set(secs, secs)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(not(unix))]
+fn set_resource_limits(
+    _prog: duct::Expression,
+    _options: &ExecOptions,
+) -> Result<duct::Expression> {
+    bail!("resource limits are only supported on Unix")
+}
+
+// the command runs in its own process group so that a kill also reaches its children, which
+// would otherwise keep the output pipe open
+#[cfg(unix)]
+fn isolate_process_group(prog: duct::Expression) -> duct::Expression {
+    use std::os::unix::process::CommandExt;
+
+    prog.before_spawn(|cmd| {
+        cmd.process_group(0);
+        Ok(())
+    })
+}
+
+#[cfg(not(unix))]
+fn isolate_process_group(prog: duct::Expression) -> duct::Expression {
+    prog
+}
+
+/// Process groups of the running commands that were moved out of the terminal's foreground
+/// group, and so no longer receive Ctrl-C from it.
+#[cfg(unix)]
+static ISOLATED_GROUPS: Mutex<Vec<libc::pid_t>> = Mutex::new(Vec::new());
+
+/// Keeps forwarding SIGINT, SIGTERM and SIGHUP to the process groups of `reader` while alive.
+/// After forwarding, scener itself reacts to the signal as it would by default.
+#[cfg(unix)]
+struct ForwardSignals(Vec<libc::pid_t>);
+
+#[cfg(unix)]
+impl ForwardSignals {
+    fn new(reader: &duct::ReaderHandle) -> Self {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+        use signal_hook::low_level::emulate_default_handler;
+
+        static FORWARDER: std::sync::Once = std::sync::Once::new();
+        FORWARDER.call_once(|| match Signals::new([SIGINT, SIGTERM, SIGHUP]) {
+            Ok(mut signals) => {
+                thread::spawn(move || {
+                    for signal in signals.forever() {
+                        for &group in ISOLATED_GROUPS.lock().expect("should not fail").iter() {
+                            unsafe { libc::killpg(group, signal) };
+                        }
+                        let _ = emulate_default_handler(signal);
+                    }
+                });
+            }
+            Err(err) => log::warn!("could not install signal handlers: {}", err),
+        });
+
+        let groups: Vec<libc::pid_t> = reader.pids().into_iter().map(|pid| pid as _).collect();
+        ISOLATED_GROUPS.lock().expect("should not fail").extend(&groups);
+        ForwardSignals(groups)
     }
+}
 
-    let mut reader = prog.reader().context("could not execute `bash`")?;
+#[cfg(unix)]
+impl Drop for ForwardSignals {
+    fn drop(&mut self) {
+        ISOLATED_GROUPS.lock().expect("should not fail").retain(|group| !self.0.contains(group));
+    }
+}
 
+#[cfg(not(unix))]
+struct ForwardSignals;
+
+#[cfg(not(unix))]
+impl ForwardSignals {
+    fn new(_reader: &duct::ReaderHandle) -> Self {
+        ForwardSignals
+    }
+}
+
+#[cfg(unix)]
+fn kill_command(reader: &duct::ReaderHandle) {
+    for pid in reader.pids() {
+        unsafe { libc::killpg(pid as _, libc::SIGKILL) };
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_command(reader: &duct::ReaderHandle) {
+    let _ = reader.kill();
+}
+
+// `PWD` may be a logical path through symlinks; the physical path is what the process actually
+// enters, and the logical one is kept when it cannot be resolved (e.g. a dangling symlink)
+fn resolve_work_dir(work_dir: &str) -> PathBuf {
+    match std::fs::canonicalize(work_dir) {
+        Ok(path) => path,
+        Err(err) => {
+            log::warn!("could not resolve working directory {}: {}", work_dir, err);
+            PathBuf::from(work_dir)
+        }
+    }
+}
+
+fn read_output(reader: &duct::ReaderHandle, mut out: impl Write) -> Result<Vec<u8>> {
     let mut output = Vec::new();
     let mut buffer = [0; 8192];
 
     loop {
-        let n = reader.read(&mut buffer).context("could not read command output")?;
+        let n = (&*reader).read(&mut buffer).context("could not read command output")?;
         if n == 0 {
             break;
         }
@@ -77,28 +378,140 @@ pub fn execute(cmd: &str, env: Environment, mut out: impl Write) -> Result<Comma
         out.write_all(read)?;
     }
 
+    Ok(output)
+}
+
+pub fn execute(
+    cmd: &str,
+    env: Environment,
+    options: &ExecOptions,
+    mut out: impl Write,
+) -> Result<CommandResult> {
+    log::debug!("executing command: {}", cmd);
+    let temp_dir = TempDir::new().context("could not create temporary directory")?;
+    let env_path = temp_dir.path().join("env");
+    log::trace!("env file: {}", env_path.display());
+
+    let cmd = format!(r#"trap "env -0 > $(printf %q "$1")" EXIT; {}"#, cmd);
+    let mut prog = cmd!("bash", "-c", cmd, "bash", env_path.as_os_str())
+        .stdin_null()
+        .stderr_to_stdout()
+        .unchecked();
+
+    let original_env = env.clone();
+    if let Some(work_dir) = env.work_dir {
+        prog = prog.dir(resolve_work_dir(&work_dir));
+    }
+    if let Some(env_vars) = env.env_vars {
+        prog = prog.full_env(env_vars);
+    }
+    if options.is_limited() {
+        prog = set_resource_limits(prog, options).context("could not set resource limits")?;
+    }
+    if options.deadline.is_some() {
+        prog = isolate_process_group(prog);
+    }
+
+    let reader = prog.reader().context("could not execute `bash`")?;
+    let _forward = options.deadline.is_some().then(|| ForwardSignals::new(&reader));
+
+    // the watchdog kills the command at the deadline unless the command finishes first
+    let timed_out = AtomicBool::new(false);
+    let output = thread::scope(|scope| {
+        let (done, watchdog) = mpsc::channel::<()>();
+        if let Some(deadline) = options.deadline {
+            let (reader, timed_out) = (&reader, &timed_out);
+            scope.spawn(move || {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                if watchdog.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                    timed_out.store(true, Ordering::SeqCst);
+                    kill_command(reader);
+                }
+            });
+        }
+        let output = read_output(&reader, &mut out);
+        drop(done);
+        output
+    })?;
+
     let status = match reader.try_wait()? {
         Some(o) => o.status,
         None => bail!("unexpected EOF while reading command output"),
     };
+    log::debug!("command exited with {} ({} bytes of output)", status, output.len());
+    if timed_out.load(Ordering::SeqCst) {
+        log::info!("command killed at the run deadline");
+    }
+    // the output is still recorded, but the command counts as failed
+    let invalid_utf8_at = match options.strict_utf8 {
+        true => std::str::from_utf8(&output).err().map(|err| err.valid_up_to()),
+        false => None,
+    };
+    let succeeded = status.success() && invalid_utf8_at.is_none();
+
+    // the output is kept even when the environment cannot be captured, e.g. after `exec`
+    let new_env = match read_env_file(&env_path) {
+        Ok(new_env) => new_env,
+        // a killed command has no chance to dump its environment
+        Err(_) if timed_out.load(Ordering::SeqCst) => original_env,
+        Err(err) => {
+            eprintln!("warning: {:#}; environment is left unchanged", err);
+            if keeps_env_file() {
+                let kept = temp_dir.keep();
+                eprintln!("env file retained at {}", kept.join("env").display());
+            }
+            original_env
+        }
+    };
 
-    let env_file = File::open(env_path).context("could not open env file")?;
-    let new_env =
-        parse_env_file(&mut BufReader::new(env_file)).context("could not parse `env` output")?;
+    Ok(CommandResult { new_env, output, succeeded, invalid_utf8_at })
+}
 
-    Ok(CommandResult {
-        new_env,
-        output: String::from_utf8_lossy(&output).to_string(),
-        succeeded: status.success(),
-    })
+pub fn execute_parallel(
+    cmds: &[String],
+    env: &Environment,
+    options: &ExecOptions,
+    jobs: usize,
+) -> Vec<Result<CommandResult>> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<CommandResult>>>> =
+        Mutex::new(cmds.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, cmds.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(cmd) = cmds.get(index) else {
+                    break;
+                };
+                let result = execute(cmd, env.clone(), options, std::io::sink());
+                results.lock().expect("should not fail")[index] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().expect("should not fail");
+    results.into_iter().map(|r| r.expect("should not fail")).collect()
+}
+
+pub fn run_hook(hook: &str, session_name: &str, session_path: &Path) -> Result<bool> {
+    let output = cmd!("bash", "-c", hook)
+        .env("SCENER_SESSION_NAME", session_name)
+        .env("SCENER_SESSION_PATH", session_path)
+        .stdin_null()
+        .unchecked()
+        .run()
+        .context("could not execute `bash`")?;
+    Ok(output.status.success())
 }
 
 #[cfg(test)]
 mod test {
+    use indoc::indoc;
+    use rstest::rstest;
 
     use super::*;
     use std::io::{BufReader, Cursor};
-    use std::path::Path;
 
     #[test]
     fn test_parse_env_file() {
@@ -128,12 +541,128 @@ mod test {
         assert_eq!(expected, actual.unwrap());
     }
 
+    #[test]
+    fn test_parse_env_file_noisy_vars() {
+        let content = b"SHLVL=2\0_=/usr/bin/env\0PWD=/path/to/pwd\0abc=123";
+        let actual = parse_env_file(&mut BufReader::new(Cursor::new(content)));
+        let expected = Environment {
+            env_vars: Some(vec![
+                ("PWD".into(), "/path/to/pwd".into()),
+                ("abc".into(), "123".into()),
+            ]),
+            work_dir: Some("/path/to/pwd".into()),
+        };
+        assert!(actual.is_ok());
+        assert_eq!(expected, actual.unwrap());
+    }
+
+    #[test]
+    fn test_parse_export_output() {
+        let content = indoc! {r#"
+            declare -x EMPTY=""
+            declare -x QUOTED="say \"hi\" to \$USER \`now\` \\o/"
+            declare -x MULTI="line1
+            line2"
+            declare -x UNSET
+            declare -x PWD="/path/to/pwd"
+        "#};
+        let actual = parse_export_output(content);
+        let expected = vec![
+            ("EMPTY".into(), "".into()),
+            ("QUOTED".into(), "say \"hi\" to $USER `now` \\o/".into()),
+            ("MULTI".into(), "line1\nline2".into()),
+            ("PWD".into(), "/path/to/pwd".into()),
+        ];
+        assert_eq!(Some(expected), actual.ok());
+    }
+
+    #[rstest]
+    #[case::no_prefix("FOO=\"bar\"\n")]
+    #[case::unterminated("declare -x FOO=\"bar\n")]
+    fn test_parse_export_output_invalid(#[case] content: &str) {
+        assert!(parse_export_output(content).is_err());
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let content = indoc! {r#"
+            # database settings
+            DB_HOST=localhost
+
+            export DB_PORT = 5432 # default port
+            GREETING="hello world"
+            ESCAPED="line1\nsay \"hi\""
+            LITERAL='$HOME \n'
+            EMPTY=
+        "#};
+        let expected: Vec<(String, String)> = vec![
+            ("DB_HOST".into(), "localhost".into()),
+            ("DB_PORT".into(), "5432".into()),
+            ("GREETING".into(), "hello world".into()),
+            ("ESCAPED".into(), "line1\nsay \"hi\"".into()),
+            ("LITERAL".into(), "$HOME \\n".into()),
+            ("EMPTY".into(), "".into()),
+        ];
+        assert_eq!(parse_dotenv(content).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::no_equals("FOO\n")]
+    #[case::empty_key("=bar\n")]
+    #[case::unterminated_double("FOO=\"bar\n")]
+    #[case::unterminated_single("FOO='bar\n")]
+    fn test_parse_dotenv_invalid(#[case] content: &str) {
+        assert!(parse_dotenv(content).is_err());
+    }
+
+    #[test]
+    fn test_parse_env_file_export_format() {
+        let content = b"declare -x SHLVL=\"2\"\ndeclare -x PWD=\"/path/to/pwd\"\n";
+        let actual = parse_env_file(&mut BufReader::new(Cursor::new(content)));
+        let expected = Environment {
+            env_vars: Some(vec![("PWD".into(), "/path/to/pwd".into())]),
+            work_dir: Some("/path/to/pwd".into()),
+        };
+        assert_eq!(Some(expected), actual.ok());
+    }
+
+    #[test]
+    fn test_initial_environment() {
+        let env = Environment::initial(None, Vec::new(), false, std::env::vars());
+        assert_eq!(env, Environment::default());
+
+        let overrides = vec![("ABC".into(), "123".into())];
+        let env = Environment::initial(Some("/tmp".into()), overrides, false, std::env::vars());
+        assert_eq!(env.work_dir, Some("/tmp".into()));
+        let env_vars = env.env_vars.unwrap();
+        assert_eq!(env_vars.iter().filter(|(k, _)| k == "ABC").count(), 1);
+        assert!(env_vars.contains(&("ABC".into(), "123".into())));
+    }
+
+    #[test]
+    fn test_initial_environment_clean() {
+        let vars = std::env::vars().chain([("SCENER_TEST_UNRELATED".into(), "1".into())]);
+        let overrides = vec![("ABC".into(), "123".into())];
+        let env = Environment::initial(None, overrides, true, vars);
+
+        let result = execute("env", env, &Default::default(), Vec::new()).unwrap();
+        let output = String::from_utf8(result.output).unwrap();
+        assert!(!output.contains("SCENER_TEST_UNRELATED="));
+        assert!(output.contains("ABC=123\n"));
+        assert!(output.lines().any(|line| line.starts_with("PATH=")));
+    }
+
     fn assert_eq_result(expected: &CommandResult, actual: &CommandResult) {
-        let CommandResult { new_env: Environment { env_vars, work_dir }, output, succeeded } =
-            actual;
+        let CommandResult {
+            new_env: Environment { env_vars, work_dir },
+            output,
+            succeeded,
+            invalid_utf8_at,
+        } = actual;
         assert_eq!(&expected.new_env.work_dir, work_dir);
         assert_eq!(&expected.output, output);
         assert_eq!(&expected.succeeded, succeeded);
+        assert_eq!(&expected.invalid_utf8_at, invalid_utf8_at);
 
         let expected_env_vars = expected.new_env.env_vars.as_ref();
 
@@ -162,7 +691,7 @@ mod test {
         };
         let mut out = Vec::new();
 
-        let actual = execute(cmd, env, &mut out);
+        let actual = execute(cmd, env, &ExecOptions::default(), &mut out);
         let expected = CommandResult {
             new_env: Environment {
                 env_vars: Some(vec![
@@ -173,13 +702,45 @@ mod test {
             },
             output: "123\n".into(),
             succeeded: true,
+            invalid_utf8_at: None,
         };
 
-        assert_eq!(Some(expected.output.clone()), String::from_utf8(out).ok());
+        assert_eq!(expected.output, out);
         assert!(actual.is_ok());
         assert_eq_result(&expected, &actual.unwrap());
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_execute_symlinked_work_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let real_path = temp_dir.path().join("real");
+        let link_path = temp_dir.path().join("link");
+        std::fs::create_dir(&real_path).unwrap();
+        std::os::unix::fs::symlink(&real_path, &link_path).unwrap();
+
+        let link = link_path.to_str().unwrap().to_owned();
+        let env = Environment {
+            env_vars: Some(vec![("PWD".to_owned(), link.clone())]),
+            work_dir: Some(link.clone()),
+        };
+        let actual = execute("touch marker && pwd", env, &ExecOptions::default(), Vec::new());
+
+        let result = actual.unwrap();
+        assert!(result.succeeded);
+        assert_eq!(result.output, format!("{}\n", link).into_bytes());
+        assert_eq!(result.new_env.work_dir, Some(link));
+        assert!(real_path.join("marker").exists());
+    }
+
+    #[test]
+    fn test_resolve_work_dir_missing() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("missing");
+        let missing = missing.to_str().unwrap();
+        assert_eq!(resolve_work_dir(missing), PathBuf::from(missing));
+    }
+
     #[test]
     fn test_execute_failed_command() {
         let path_to_string = |p: &Path| p.to_str().unwrap().to_owned();
@@ -194,7 +755,7 @@ mod test {
         };
         let mut out = Vec::new();
 
-        let actual = execute(cmd, env, &mut out);
+        let actual = execute(cmd, env, &ExecOptions::default(), &mut out);
         let expected = CommandResult {
             new_env: Environment {
                 env_vars: Some(vec![("PWD".to_owned(), path_to_string(temp_path))]),
@@ -202,10 +763,80 @@ mod test {
             },
             output: "123\n".into(),
             succeeded: false,
+            invalid_utf8_at: None,
         };
 
-        assert_eq!(Some(expected.output.clone()), String::from_utf8(out).ok());
+        assert_eq!(expected.output, out);
         assert!(actual.is_ok());
         assert_eq_result(&expected, &actual.unwrap());
     }
+
+    #[test]
+    fn test_execute_without_env_file() {
+        let env = Environment {
+            env_vars: Some(vec![("ABC".to_owned(), "1".to_owned())]),
+            work_dir: None,
+        };
+        let mut out = Vec::new();
+
+        // `exec` replaces the shell, so the EXIT trap never writes the env file
+        let cmd = "export ABC=2 && exec echo replaced";
+        let actual = execute(cmd, env.clone(), &ExecOptions::default(), &mut out).unwrap();
+
+        assert_eq!(out, b"replaced\n");
+        assert_eq!(actual.output, b"replaced\n");
+        assert!(actual.succeeded);
+        assert_eq!(actual.new_env, env);
+    }
+
+    #[test]
+    fn test_execute_strict_utf8() {
+        let cmd = r"printf 'ok\xff\n'";
+        let options = ExecOptions { strict_utf8: true, ..Default::default() };
+        let result = execute(cmd, Environment::default(), &options, Vec::new()).unwrap();
+        assert!(!result.succeeded);
+        assert_eq!(result.invalid_utf8_at, Some(2));
+        assert_eq!(result.output, b"ok\xff\n");
+
+        let result = execute(cmd, Environment::default(), &ExecOptions::default(), Vec::new());
+        assert_eq!(result.unwrap().output, b"ok\xff\n");
+
+        let result = execute("echo ok", Environment::default(), &options, Vec::new());
+        assert!(result.unwrap().succeeded);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_execute_mem_limit() {
+        let cmd = r#"bash -c 'x=$(head -c 1073741824 /dev/zero | tr "\0" a)' && echo done"#;
+        let options = ExecOptions { mem_limit: Some(256 * 1024 * 1024), ..Default::default() };
+        let mut out = Vec::new();
+
+        let actual = execute(cmd, Environment::default(), &options, &mut out).unwrap();
+
+        assert!(!actual.succeeded);
+        assert!(!String::from_utf8_lossy(&actual.output).contains("done"));
+    }
+
+    #[test]
+    fn test_run_hook() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let marker = temp_dir.path().join("marker");
+        let hook = format!(
+            r#"echo "$SCENER_SESSION_NAME $SCENER_SESSION_PATH" > {}"#,
+            marker.to_str().unwrap()
+        );
+
+        let actual = run_hook(&hook, "test", Path::new("/path/to/test.json"));
+
+        assert_eq!(actual.ok(), Some(true));
+        let content = std::fs::read_to_string(marker).unwrap();
+        assert_eq!(content, "test /path/to/test.json\n");
+    }
+
+    #[test]
+    fn test_run_hook_failed() {
+        let actual = run_hook("false", "test", Path::new("/path/to/test.json"));
+        assert_eq!(actual.ok(), Some(false));
+    }
 }
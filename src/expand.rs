@@ -0,0 +1,83 @@
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+pub fn expand_vars<F: Fn(&str) -> Option<String>>(
+    s: &str,
+    home: Option<&str>,
+    lookup: F,
+) -> (String, Vec<String>) {
+    let mut expanded = String::new();
+    let mut undefined = Vec::new();
+
+    let mut rest = s;
+    if let (Some(home), Some(stripped)) = (home, s.strip_prefix('~')) {
+        if stripped.is_empty() || stripped.starts_with('/') {
+            expanded.push_str(home);
+            rest = stripped;
+        }
+    }
+
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        let (name, remaining) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+
+        if name.is_empty() {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+
+        match lookup(name) {
+            Some(value) => expanded.push_str(&value),
+            None => undefined.push(name.to_owned()),
+        }
+        rest = remaining;
+    }
+    expanded.push_str(rest);
+
+    (expanded, undefined)
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "FOO" => Some("foo".into()),
+            "PATH" => Some("/usr/bin".into()),
+            _ => None,
+        }
+    }
+
+    #[rstest]
+    #[case::plain("abc", "abc", &[])]
+    #[case::tilde("~/projects", "/home/user/projects", &[])]
+    #[case::bare_tilde("~", "/home/user", &[])]
+    #[case::tilde_user("~user/projects", "~user/projects", &[])]
+    #[case::inner_tilde("a/~/b", "a/~/b", &[])]
+    #[case::var("$PATH:/opt/bin", "/usr/bin:/opt/bin", &[])]
+    #[case::braced("${FOO}bar", "foobar", &[])]
+    #[case::undefined("a$UNDEFINED/b", "a/b", &["UNDEFINED"])]
+    #[case::lone_dollar("a$ b$", "a$ b$", &[])]
+    #[case::unclosed_brace("${FOO", "${FOO", &[])]
+    fn test_expand_vars(#[case] s: &str, #[case] expected: &str, #[case] undefined: &[&str]) {
+        let (actual, actual_undefined) = expand_vars(s, Some("/home/user"), lookup);
+        assert_eq!(actual, expected);
+        assert_eq!(actual_undefined, undefined);
+    }
+}
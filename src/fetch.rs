@@ -0,0 +1,102 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::read_script;
+
+pub const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+pub const MAX_SCRIPT_SIZE: u64 = 1024 * 1024;
+
+/// Downloads a script over HTTP(S) and reads it like a script file.
+pub fn fetch_script(url: &str, timeout: Duration, max_size: u64) -> Result<Vec<String>> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let response = agent.get(url).call().with_context(|| format!("could not fetch {}", url))?;
+
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(max_size + 1)
+        .read_to_end(&mut body)
+        .with_context(|| format!("could not read response from {}", url))?;
+    if body.len() as u64 > max_size {
+        bail!("script at {} is larger than {} bytes", url, max_size);
+    }
+
+    read_script(body.as_slice()).with_context(|| format!("could not read script from {}", url))
+}
+
+/// Shows the commands and asks whether to run them. Anything but `y`/`yes` declines.
+pub fn confirm_commands<F: FnMut() -> Result<Option<String>>>(
+    commands: &[String],
+    mut read_line: F,
+    mut out: impl Write,
+) -> Result<bool> {
+    for command in commands {
+        writeln!(&mut out, "$ {}", command)?;
+    }
+    write!(&mut out, "run these {} commands? [y/N] ", commands.len())?;
+    out.flush()?;
+
+    let answer = read_line()?.unwrap_or_default();
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    // serves a single request with `body` and returns the URL to fetch it from
+    fn serve_once(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/script.sh", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            (&stream).write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn test_fetch_script() {
+        let url = serve_once("#!/bin/bash\necho hello\n\necho world\n");
+        let commands = fetch_script(&url, FETCH_TIMEOUT, MAX_SCRIPT_SIZE).unwrap();
+        assert_eq!(commands, vec!["echo hello", "echo world"]);
+    }
+
+    #[test]
+    fn test_fetch_script_too_large() {
+        let url = serve_once("echo hello\n");
+        assert!(fetch_script(&url, FETCH_TIMEOUT, 4).is_err());
+    }
+
+    #[rstest]
+    #[case::yes(Some("y"), true)]
+    #[case::yes_long(Some(" Yes \n"), true)]
+    #[case::no(Some("n"), false)]
+    #[case::empty(Some(""), false)]
+    #[case::eof(None, false)]
+    fn test_confirm_commands(#[case] answer: Option<&str>, #[case] expected: bool) {
+        let commands = vec!["echo hi".to_owned()];
+        let mut out = Vec::new();
+        let actual = confirm_commands(&commands, || Ok(answer.map(ToOwned::to_owned)), &mut out);
+        assert_eq!(actual.unwrap(), expected);
+        assert_eq!(String::from_utf8(out).unwrap(), "$ echo hi\nrun these 1 commands? [y/N] ");
+    }
+}
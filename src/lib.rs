@@ -1,17 +1,45 @@
+mod archive;
 mod cli;
+#[cfg(feature = "clipboard")]
+mod clipboard;
 mod dirs;
+mod editor;
+mod encoding;
 mod exec;
+mod expand;
+#[cfg(feature = "url")]
+mod fetch;
+mod logger;
+mod menu;
+#[cfg(feature = "picker")]
+mod picker;
 mod printer;
 mod reference;
 mod scanner;
 mod script;
+mod search;
 mod session;
+mod watch;
 
+pub use archive::*;
 pub use cli::*;
+#[cfg(feature = "clipboard")]
+pub use clipboard::*;
 pub use dirs::*;
+pub use editor::*;
+pub use encoding::*;
 pub use exec::*;
+pub use expand::*;
+#[cfg(feature = "url")]
+pub use fetch::*;
+pub use logger::*;
+pub use menu::*;
+#[cfg(feature = "picker")]
+pub use picker::*;
 pub use printer::*;
 pub use reference::*;
 pub use scanner::*;
 pub use script::*;
+pub use search::*;
 pub use session::*;
+pub use watch::*;
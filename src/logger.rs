@@ -0,0 +1,51 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Maps the number of `-v` flags to a log level; warnings and errors are always enabled.
+pub fn level_filter(verbose: u8) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub fn init_logger(verbose: u8) {
+    // a logger may already be installed, e.g. when called more than once in tests
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level_filter(verbose));
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::quiet(0, LevelFilter::Warn)]
+    #[case::info(1, LevelFilter::Info)]
+    #[case::debug(2, LevelFilter::Debug)]
+    #[case::trace(5, LevelFilter::Trace)]
+    fn test_level_filter(#[case] verbose: u8, #[case] expected: LevelFilter) {
+        assert_eq!(level_filter(verbose), expected);
+    }
+}
@@ -0,0 +1,58 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+/// Renders commands as a numbered menu, numbered as `show --number` does.
+pub fn render_command_menu(commands: &[&str], mut out: impl Write) -> std::io::Result<()> {
+    for (index, command) in commands.iter().enumerate() {
+        writeln!(&mut out, "{:>2}: {}", index + 1, command)?;
+    }
+    Ok(())
+}
+
+pub fn parse_menu_selection(line: &str) -> Result<usize> {
+    match line.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => bail!("invalid selection `{}`", line.trim()),
+    }
+}
+
+/// Returns the command numbered `index` (starting from 1) in the menu.
+pub fn select_command<'a>(commands: &[&'a str], index: usize) -> Result<&'a str> {
+    match index.checked_sub(1).and_then(|i| commands.get(i)) {
+        Some(command) => Ok(command),
+        None => bail!("command {} is out of range ({} commands)", index, commands.len()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::first(1, Some("cargo build"))]
+    #[case::last(3, Some("git status"))]
+    #[case::zero(0, None)]
+    #[case::out_of_range(4, None)]
+    fn test_select_command(#[case] index: usize, #[case] expected: Option<&str>) {
+        let commands = ["cargo build", "cargo test", "git status"];
+        assert_eq!(select_command(&commands, index).ok(), expected);
+    }
+
+    #[rstest]
+    #[case::number(" 2\n", Some(2))]
+    #[case::zero("0", None)]
+    #[case::text("git", None)]
+    fn test_parse_menu_selection(#[case] line: &str, #[case] expected: Option<usize>) {
+        assert_eq!(parse_menu_selection(line).ok(), expected);
+    }
+
+    #[test]
+    fn test_render_command_menu() {
+        let mut out = Vec::new();
+        render_command_menu(&["cargo build", "git status"], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), " 1: cargo build\n 2: git status\n");
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::SessionSummary;
+
+const MAX_CANDIDATES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickerEntry {
+    pub name: String,
+    pub preview: String,
+}
+
+impl From<&SessionSummary> for PickerEntry {
+    fn from(summary: &SessionSummary) -> Self {
+        let commands: Vec<&str> = summary.records.iter().map(|r| r.command.as_str()).collect();
+        PickerEntry { name: summary.name.clone(), preview: commands.join("; ") }
+    }
+}
+
+/// Matches `query` as a case-insensitive subsequence of `text`. Returns the length of the
+/// matched span, so a smaller score means a tighter match.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut start = None;
+    let mut pos = 0;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = text[pos..].iter().position(|&c| c == q)? + pos;
+        start.get_or_insert(found);
+        pos = found + 1;
+    }
+    Some(start.map_or(0, |start| pos - start))
+}
+
+pub fn filter_entries<'a>(entries: &'a [PickerEntry], query: &str) -> Vec<&'a PickerEntry> {
+    let mut scored: Vec<(usize, &PickerEntry)> = entries
+        .iter()
+        .filter_map(|entry| {
+            let text = format!("{} {}", entry.name, entry.preview);
+            fuzzy_score(query, &text).map(|score| (score, entry))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| *score);
+    scored.into_iter().map(|(_, entry)| entry).collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum PickInput {
+    Select(usize),
+    Query(String),
+}
+
+fn parse_pick_input(line: &str) -> PickInput {
+    let line = line.trim();
+    if line.is_empty() {
+        return PickInput::Select(0);
+    }
+    match line.parse::<usize>() {
+        Ok(n) if n > 0 => PickInput::Select(n - 1),
+        _ => PickInput::Query(line.to_owned()),
+    }
+}
+
+fn render_candidates(candidates: &[&PickerEntry], mut out: impl Write) -> std::io::Result<()> {
+    for (index, entry) in candidates.iter().take(MAX_CANDIDATES).enumerate() {
+        writeln!(&mut out, "{:>2}: {}  {}", index + 1, entry.name, entry.preview)?;
+    }
+    if candidates.len() > MAX_CANDIDATES {
+        writeln!(&mut out, "    ... ({} more)", candidates.len() - MAX_CANDIDATES)?;
+    }
+    writeln!(&mut out, "(type a number to select, text to filter, or press enter for 1)")
+}
+
+/// Lets the user narrow down `entries` by typing queries, and returns the selected name.
+/// Returns `None` when input ends before a selection is made.
+pub fn pick_session<F: FnMut() -> Result<Option<String>>>(
+    entries: &[PickerEntry],
+    mut read_line: F,
+    mut out: impl Write,
+) -> Result<Option<String>> {
+    let mut query = String::new();
+
+    loop {
+        let candidates = filter_entries(entries, &query);
+        if candidates.is_empty() {
+            writeln!(&mut out, "no sessions match `{}`", query)?;
+            query.clear();
+            continue;
+        }
+        render_candidates(&candidates, &mut out)?;
+
+        let line = match read_line()? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        match parse_pick_input(&line) {
+            PickInput::Select(index) => match candidates.get(index) {
+                Some(entry) => return Ok(Some(entry.name.clone())),
+                None => writeln!(&mut out, "no candidate numbered {}", index + 1)?,
+            },
+            PickInput::Query(q) => query = q,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn entries() -> Vec<PickerEntry> {
+        vec![
+            PickerEntry { name: "s1".into(), preview: "cargo build; cargo test".into() },
+            PickerEntry { name: "s2".into(), preview: "git status".into() },
+            PickerEntry { name: "s3".into(), preview: "c a r g o".into() },
+        ]
+    }
+
+    #[rstest]
+    #[case::empty("", "abc", Some(0))]
+    #[case::exact("abc", "xabcx", Some(3))]
+    #[case::spread("ac", "abc", Some(3))]
+    #[case::case_insensitive("AB", "xaby", Some(2))]
+    #[case::missing("abd", "abc", None)]
+    fn test_fuzzy_score(#[case] query: &str, #[case] text: &str, #[case] expected: Option<usize>) {
+        assert_eq!(fuzzy_score(query, text), expected);
+    }
+
+    #[test]
+    fn test_filter_entries() {
+        let entries = entries();
+        let names: Vec<&str> =
+            filter_entries(&entries, "cargo").iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["s1", "s3"]);
+    }
+
+    #[rstest]
+    #[case::empty("", PickInput::Select(0))]
+    #[case::number(" 2 ", PickInput::Select(1))]
+    #[case::zero("0", PickInput::Query("0".into()))]
+    #[case::query("git", PickInput::Query("git".into()))]
+    fn test_parse_pick_input(#[case] line: &str, #[case] expected: PickInput) {
+        assert_eq!(parse_pick_input(line), expected);
+    }
+
+    #[rstest]
+    #[case::default(&[""], Some("s1"))]
+    #[case::number(&["3"], Some("s3"))]
+    #[case::query(&["git", ""], Some("s2"))]
+    #[case::query_then_number(&["cargo", "2"], Some("s3"))]
+    #[case::out_of_range(&["9", "2"], Some("s2"))]
+    #[case::no_match(&["zzz", "2"], Some("s2"))]
+    #[case::eof(&["git"], None)]
+    fn test_pick_session(#[case] inputs: &[&str], #[case] expected: Option<&str>) {
+        let mut inputs = inputs.iter().map(|s| s.to_string());
+        let actual = pick_session(&entries(), || Ok(inputs.next()), Vec::new()).unwrap();
+        assert_eq!(actual.as_deref(), expected);
+    }
+}
@@ -1,73 +1,646 @@
-use std::io::Write;
+use std::io::{stdout, IsTerminal, Write};
+use std::time::Duration;
 
 use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use terminal_size::{terminal_size, Height, Width};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::{CommandStatus, Session};
+use crate::{
+    context_window, format_annotation, CommandRecord, CommandStatus, SearchQuery, Session,
+    SessionSummary,
+};
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// Printed in place of the output of a command that could not be run at all.
+const ERRORED_MARKER: &str = "(errored)";
+
+/// Pause between replayed commands; sessions carry no per-command timing to replay.
+const PLAY_DELAY: Duration = Duration::from_millis(500);
 
 pub fn needs_newline(s: &str) -> bool {
     !s.is_empty() && !s.ends_with('\n')
 }
 
+pub fn contains_ansi_escape(s: &str) -> bool {
+    s.contains("\x1b[")
+}
+
+/// Returns the terminal (width, height) if STDOUT is a terminal.
+pub fn terminal_dimensions() -> Option<(u16, u16)> {
+    dimensions_if_terminal(stdout().is_terminal(), terminal_size())
+}
+
+fn dimensions_if_terminal(tty: bool, size: Option<(Width, Height)>) -> Option<(u16, u16)> {
+    size.filter(|_| tty).map(|(Width(w), Height(h))| (w, h))
+}
+
+pub fn terminal_width() -> Option<usize> {
+    if !stdout().is_terminal() {
+        return None;
+    }
+    terminal_size().map(|(Width(w), _)| w as usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderConfig {
+    pub tty: bool,
+    pub width: Option<usize>,
+    /// Passes ANSI escapes in recorded outputs through instead of stripping them
+    pub color: bool,
+}
+
+impl RenderConfig {
+    pub fn detect(plain: bool) -> Self {
+        Self::resolve(plain, stdout().is_terminal(), terminal_width(), ColorMode::Auto.detect())
+    }
+
+    /// Renders as for a pipe, whatever STDOUT is.
+    pub fn plain() -> Self {
+        RenderConfig { tty: false, width: None, color: false }
+    }
+
+    fn resolve(plain: bool, tty: bool, width: Option<usize>, color: bool) -> Self {
+        match plain {
+            true => Self::plain(),
+            false => RenderConfig { tty, width, color },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Decides whether to color the output. An explicit `always`/`never` wins, then
+    /// `CLICOLOR_FORCE` (unless `0`), then `NO_COLOR` (set to anything), then TTY detection.
+    pub fn resolve(self, clicolor_force: Option<&str>, no_color: Option<&str>, tty: bool) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto if clicolor_force.is_some_and(|v| v != "0") => true,
+            ColorMode::Auto if no_color.is_some() => false,
+            ColorMode::Auto => tty,
+        }
+    }
+
+    pub fn detect(self) -> bool {
+        let clicolor_force = std::env::var("CLICOLOR_FORCE").ok();
+        let no_color = std::env::var("NO_COLOR").ok();
+        self.resolve(clicolor_force.as_deref(), no_color.as_deref(), stdout().is_terminal())
+    }
+}
+
+/// Truncates `line` to `width` terminal columns, ending it with an ellipsis if `width` leaves
+/// room for one.
+pub fn truncate_line(line: &str, width: usize) -> String {
+    const ELLIPSIS: &str = "...";
+    if line.width() <= width {
+        return line.to_owned();
+    }
+    let (budget, ellipsis) = match width.checked_sub(ELLIPSIS.len()) {
+        Some(budget) => (budget, ELLIPSIS),
+        None => (width, ""),
+    };
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in line.chars() {
+        used += c.width().unwrap_or(0);
+        if used > budget {
+            break;
+        }
+        truncated.push(c);
+    }
+    truncated.push_str(ellipsis);
+    truncated
+}
+
 fn format_datetime(dt: DateTime<Utc>) -> String {
     let local: DateTime<Local> = dt.into();
     local.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// Describes how long ago `dt` was, e.g. `3 minutes ago` or `yesterday`.
+pub fn format_relative(dt: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let plural = |n: i64, unit: &str| match n {
+        1 => format!("1 {} ago", unit),
+        n => format!("{} {}s ago", n, unit),
+    };
+    let elapsed = now.signed_duration_since(dt);
+    match elapsed.num_seconds() {
+        ..60 => "just now".to_owned(),
+        60..3600 => plural(elapsed.num_minutes(), "minute"),
+        3600..86400 => plural(elapsed.num_hours(), "hour"),
+        86400..172800 => "yesterday".to_owned(),
+        _ => plural(elapsed.num_days(), "day"),
+    }
+}
+
+fn format_time(dt: DateTime<Utc>, relative_to: Option<DateTime<Utc>>) -> String {
+    match relative_to {
+        Some(now) => format_relative(dt, now),
+        None => format_datetime(dt),
+    }
+}
+
+pub fn strip_ansi(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates until a final byte in 0x40..=0x7e
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: terminated by BEL or ST (ESC \)
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stripped
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct PrintOptions {
+    pub strip_ansi: bool,
+    pub number: bool,
+    pub no_banner: bool,
+    pub head: Option<usize>,
+    pub tail: Option<usize>,
+    /// Shows the recorded time relative to this instant instead of as a date
+    pub relative_to: Option<DateTime<Utc>>,
+    pub wrap: Option<Wrap>,
+    /// Prints the output only for commands with these statuses, if set
+    pub unfolded: Option<Vec<CommandStatus>>,
+    /// Number of leading commands left out because an earlier session shares them, which is
+    /// noted below the banner
+    pub omitted: usize,
+    /// Resets the terminal attributes after output containing ANSI escapes, for a color terminal
+    pub color: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wrap {
+    pub width: usize,
+    /// Breaks lines at exactly `width` characters instead of at word boundaries
+    pub hard: bool,
+}
+
+pub fn wrap_line(line: &str, wrap: Wrap) -> Vec<String> {
+    let width = wrap.width.max(1);
+    if wrap.hard {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            return vec![String::new()];
+        }
+        return chars.chunks(width).map(|chunk| chunk.iter().collect()).collect();
+    }
+    textwrap::wrap(line, width).into_iter().map(|part| part.into_owned()).collect()
+}
+
+fn wrap_text(text: &str, wrap: Wrap) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (line, newline) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+        wrapped.push_str(&wrap_line(line, wrap).join("\n"));
+        wrapped.push_str(newline);
+    }
+    wrapped
+}
+
+fn print_banner(
+    session: &Session,
+    options: &PrintOptions,
+    mut stderr: impl Write,
+) -> std::io::Result<()> {
+    let time = format_time(session.recorded_at, options.relative_to);
+    writeln!(&mut stderr, "session {} ({})", session.name, time)?;
+    if options.omitted > 0 {
+        writeln!(&mut stderr, "({} shared setup commands omitted)", options.omitted)?;
+    }
+    Ok(())
+}
+
+fn print_record(
+    record: &CommandRecord,
+    index: Option<usize>,
+    options: &PrintOptions,
+    mut stdout: impl Write,
+) -> std::io::Result<()> {
+    if record.status == CommandStatus::Annotation {
+        return writeln!(&mut stdout, "# {}", record.command);
+    }
+    if let Some(index) = index {
+        write!(&mut stdout, "[{}] ", index)?;
+    }
+    writeln!(&mut stdout, "$ {}", record.command)?;
+    if record.status == CommandStatus::Errored {
+        return writeln!(&mut stdout, "{}", ERRORED_MARKER);
+    }
+    if !record.status.is_executed() {
+        return Ok(());
+    }
+    if options.unfolded.as_ref().is_some_and(|statuses| !statuses.contains(&record.status)) {
+        return Ok(());
+    }
+    let text = record.output_text();
+    if options.strip_ansi || options.wrap.is_some() {
+        let mut output = match options.strip_ansi {
+            true => strip_ansi(&text),
+            false => text.into_owned(),
+        };
+        if let Some(wrap) = options.wrap {
+            output = wrap_text(&output, wrap);
+        }
+        write!(&mut stdout, "{}", output)?;
+        if options.color && contains_ansi_escape(&output) {
+            write!(&mut stdout, "{}", ANSI_RESET)?;
+        }
+        if needs_newline(&output) {
+            writeln!(&mut stdout)?;
+        }
+        return Ok(());
+    }
+    stdout.write_all(&record.output)?;
+    if options.color && contains_ansi_escape(&text) {
+        write!(&mut stdout, "{}", ANSI_RESET)?;
+    }
+    if needs_newline(&text) {
+        writeln!(&mut stdout)?;
+    }
+    Ok(())
+}
+
 pub fn print_session(
     session: Session,
+    options: &PrintOptions,
+    stdout: impl Write,
+    stderr: impl Write,
+) -> std::io::Result<()> {
+    print_session_filtered(session, options, None, stdout, stderr)
+}
+
+/// Prints a session like `print_session`, but only the commands accepted by `filter`. Filtered
+/// out commands still count toward numbering, and annotations go with the command they precede.
+pub fn print_session_filtered(
+    session: Session,
+    options: &PrintOptions,
+    filter: Option<&dyn Fn(&CommandRecord) -> bool>,
     mut stdout: impl Write,
     mut stderr: impl Write,
 ) -> std::io::Result<()> {
-    writeln!(&mut stderr, "session {} ({})", session.name, format_datetime(session.recorded_at))?;
+    if !options.no_banner {
+        print_banner(&session, options, &mut stderr)?;
+        if let Some(description) = &session.description {
+            writeln!(&mut stderr, "{}", description)?;
+        }
+    }
 
-    let iter = session.records.into_iter();
-    let iter = iter.filter(|r| r.status.is_executed());
-    let mut iter = iter.peekable();
+    let total = session.records.iter().filter(|r| r.status.is_attempted()).count();
+    let shown = match (options.head, options.tail) {
+        (Some(n), _) => 0..n.min(total),
+        (None, Some(n)) => total.saturating_sub(n)..total,
+        (None, None) => 0..total,
+    };
+    let visible: Vec<bool> = session
+        .records
+        .iter()
+        .filter(|r| r.status.is_attempted())
+        .enumerate()
+        .map(|(i, r)| shown.contains(&i) && filter.is_none_or(|f| f(r)))
+        .collect();
+    let trailing_visible = shown.end == total && filter.is_none();
 
-    while let Some(record) = iter.next() {
-        writeln!(&mut stdout, "$ {}", record.command)?;
-        write!(&mut stdout, "{}", record.output)?;
-        if needs_newline(&record.output) {
+    // each record stores the working directory after it ran, i.e. where the next one runs
+    let mut last_dir = session.work_dir.clone();
+    let mut shown_dir = session.work_dir.clone();
+    let mut count = 0;
+    let mut first = true;
+
+    for record in session.records {
+        let (index, dir) = match record.status {
+            // an annotation goes with the command that follows it
+            CommandStatus::Annotation => {
+                if !visible.get(count).copied().unwrap_or(trailing_visible) {
+                    continue;
+                }
+                (None, None)
+            }
+            _ if record.status.is_attempted() => {
+                let is_visible = visible[count];
+                count += 1;
+                let dir = last_dir.clone();
+                if record.work_dir.is_some() {
+                    last_dir.clone_from(&record.work_dir);
+                }
+                if !is_visible {
+                    continue;
+                }
+                (options.number.then_some(count), dir.filter(|d| shown_dir.as_ref() != Some(d)))
+            }
+            _ => continue,
+        };
+        if !first {
+            writeln!(&mut stdout)?;
+        }
+        first = false;
+        if let Some(dir) = dir {
+            writeln!(&mut stdout, "[{}]", dir)?;
+            shown_dir = Some(dir);
+        }
+        print_record(&record, index, options, &mut stdout)?;
+    }
+
+    if !session.notes.is_empty() {
+        if !first {
             writeln!(&mut stdout)?;
         }
-        if iter.peek().is_some() {
+        writeln!(&mut stdout, "Notes:")?;
+        for (noted_at, text) in &session.notes {
+            writeln!(&mut stdout, "[{}] {}", format_time(*noted_at, options.relative_to), text)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the session like a recorded demo, calling `sleep` between commands. `speed` divides
+/// the pauses, and 0 plays the session without pausing.
+pub fn play_session(
+    session: Session,
+    options: &PrintOptions,
+    speed: f64,
+    mut sleep: impl FnMut(Duration),
+    mut stdout: impl Write,
+    mut stderr: impl Write,
+) -> std::io::Result<()> {
+    if !options.no_banner {
+        print_banner(&session, options, &mut stderr)?;
+    }
+
+    let delay = (speed > 0.0).then(|| PLAY_DELAY.div_f64(speed));
+    // the same records as `print_session` are shown, numbered the same way
+    let mut count = 0;
+    let mut first = true;
+    for record in &session.records {
+        let index = match record.status {
+            CommandStatus::Annotation => None,
+            _ if record.status.is_attempted() => {
+                count += 1;
+                options.number.then_some(count)
+            }
+            _ => continue,
+        };
+        if !first {
+            if let Some(delay) = delay {
+                sleep(delay);
+            }
             writeln!(&mut stdout)?;
         }
+        first = false;
+        print_record(record, index, options, &mut stdout)?;
+        stdout.flush()?;
     }
 
     Ok(())
 }
 
+pub fn print_session_grouped(
+    session: Session,
+    options: &PrintOptions,
+    mut stdout: impl Write,
+    mut stderr: impl Write,
+) -> std::io::Result<()> {
+    if !options.no_banner {
+        print_banner(&session, options, &mut stderr)?;
+    }
+
+    let groups = [
+        (CommandStatus::Failed, "Failed"),
+        (CommandStatus::Errored, "Errored"),
+        (CommandStatus::Succeeded, "Succeeded"),
+        (CommandStatus::Skipped, "Skipped"),
+        (CommandStatus::Annotation, "Notes"),
+    ];
+    let mut first = true;
+
+    for (status, label) in groups {
+        let records: Vec<&CommandRecord> =
+            session.records.iter().filter(|r| r.status == status).collect();
+        if records.is_empty() {
+            continue;
+        }
+        if !first {
+            writeln!(&mut stdout)?;
+        }
+        first = false;
+
+        writeln!(&mut stdout, "# {}", label)?;
+        let mut iter = records.into_iter().peekable();
+        while let Some(record) = iter.next() {
+            print_record(record, None, options, &mut stdout)?;
+            if iter.peek().is_some() {
+                writeln!(&mut stdout)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ScriptOptions {
+    /// Starts the script with a bash shebang and `set -e`
+    pub shebang: bool,
+    /// Leaves out the commands that did not succeed
+    pub only_succeeded: bool,
+}
+
 pub fn print_session_script(
     session: Session,
+    script: ScriptOptions,
+    options: &PrintOptions,
     mut stdout: impl Write,
     mut stderr: impl Write,
 ) -> std::io::Result<()> {
-    writeln!(&mut stderr, "session {} ({})", session.name, format_datetime(session.recorded_at))?;
+    if !options.no_banner {
+        print_banner(&session, options, &mut stderr)?;
+    }
+    if script.shebang {
+        writeln!(&mut stdout, "#!/usr/bin/env bash")?;
+        writeln!(&mut stdout, "set -e")?;
+    }
     for record in session.records.into_iter() {
-        writeln!(&mut stdout, "{}", record.command)?;
+        match record.status {
+            CommandStatus::Annotation => {
+                writeln!(&mut stdout, "{}", format_annotation(&record.command))?
+            }
+            _ if script.only_succeeded && !record.status.is_succeeded() => continue,
+            _ => writeln!(&mut stdout, "{}", record.command)?,
+        }
+    }
+    Ok(())
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+pre { background: #f6f8fa; border-left: 4px solid #2da44e; padding: 0.5em 1em; }
+pre.failed { border-left-color: #cf222e; color: #cf222e; }
+pre.errored { border-left-color: #9a6700; color: #9a6700; }
+.command { font-weight: bold; }
+";
+
+// TAP descriptions are single lines, and `#` would start a directive
+fn tap_description(command: &str) -> String {
+    command.lines().collect::<Vec<_>>().join(" ").replace('#', "\\#")
+}
+
+/// Prints one TAP (Test Anything Protocol) test line per command, followed by the plan.
+/// Annotations become TAP comments.
+pub fn print_session_tap(session: Session, mut stdout: impl Write) -> std::io::Result<()> {
+    let mut n = 0;
+    for record in &session.records {
+        let description = tap_description(&record.command);
+        match record.status {
+            CommandStatus::Annotation => {
+                writeln!(&mut stdout, "# {}", record.command.lines().next().unwrap_or(""))?;
+                continue;
+            }
+            CommandStatus::Succeeded => writeln!(&mut stdout, "ok {} - {}", n + 1, description)?,
+            CommandStatus::Failed | CommandStatus::Errored => {
+                writeln!(&mut stdout, "not ok {} - {}", n + 1, description)?
+            }
+            CommandStatus::Skipped => {
+                writeln!(&mut stdout, "ok {} - {} # SKIP", n + 1, description)?
+            }
+        }
+        n += 1;
+    }
+    writeln!(&mut stdout, "1..{}", n)
+}
+
+pub fn print_session_html(session: Session, mut stdout: impl Write) -> std::io::Result<()> {
+    let title = format!("session {} ({})", session.name, format_datetime(session.recorded_at));
+    let title = escape_html(&title);
+
+    writeln!(&mut stdout, "<!DOCTYPE html>")?;
+    writeln!(&mut stdout, "<html>")?;
+    writeln!(&mut stdout, "<head>")?;
+    writeln!(&mut stdout, "<meta charset=\"utf-8\">")?;
+    writeln!(&mut stdout, "<title>{}</title>", title)?;
+    write!(&mut stdout, "<style>\n{}</style>\n", HTML_STYLE)?;
+    writeln!(&mut stdout, "</head>")?;
+    writeln!(&mut stdout, "<body>")?;
+    writeln!(&mut stdout, "<h1>{}</h1>", title)?;
+
+    for record in session.records.iter().filter(|r| r.status.is_attempted()) {
+        let class = match record.status {
+            CommandStatus::Failed => "failed",
+            CommandStatus::Errored => "errored",
+            _ => "succeeded",
+        };
+        write!(&mut stdout, "<pre class=\"{}\">", class)?;
+        writeln!(&mut stdout, "<span class=\"command\">$ {}</span>", escape_html(&record.command))?;
+        if record.status == CommandStatus::Errored {
+            writeln!(&mut stdout, "{}", escape_html(ERRORED_MARKER))?;
+        }
+        let text = record.output_text();
+        write!(&mut stdout, "{}", escape_html(&strip_ansi(&text)))?;
+        if needs_newline(&text) {
+            writeln!(&mut stdout)?;
+        }
+        writeln!(&mut stdout, "</pre>")?;
     }
+
+    writeln!(&mut stdout, "</body>")?;
+    writeln!(&mut stdout, "</html>")?;
+
     Ok(())
 }
 
+const DESCRIPTION_WIDTH: usize = 40;
+
 pub fn print_session_brief(
     session: Session,
     key: usize,
     max: Option<usize>,
+    width: Option<usize>,
+    relative_to: Option<DateTime<Utc>>,
+    preview: bool,
     mut stdout: impl Write,
 ) -> std::io::Result<()> {
-    writeln!(&mut stdout, "{}: {} ({})", key, session.name, format_datetime(session.recorded_at))?;
+    let time = format_time(session.recorded_at, relative_to);
+    let mut header = format!("{}: {} ({})", key, session.name, time);
+    if let Some(description) = session.description.as_deref().and_then(|d| d.lines().next()) {
+        header.push_str(" - ");
+        header.push_str(&truncate_line(description, DESCRIPTION_WIDTH));
+    }
+    writeln!(&mut stdout, "{}", header)?;
 
-    let len = session.records.len();
+    let summary = session.summary(preview);
+    let len = summary.records.len();
     let n = max.unwrap_or(len).min(len);
     let rem = len - n;
 
-    for record in session.records.iter().take(n) {
+    for record in summary.records.iter().take(n) {
         let marker = match record.status {
             CommandStatus::Succeeded | CommandStatus::Failed => "$",
             CommandStatus::Skipped => "?",
+            CommandStatus::Annotation => "#",
+            CommandStatus::Errored => "!",
         };
-        writeln!(&mut stdout, "    {} {}", marker, record.command)?;
+        let mut line = format!("    {} {}", marker, record.command);
+        if let Some(output_preview) = &record.output_preview {
+            line.push_str(" → ");
+            line.push_str(output_preview);
+        }
+        match width {
+            Some(width) => writeln!(&mut stdout, "{}", truncate_line(&line, width))?,
+            None => writeln!(&mut stdout, "{}", line)?,
+        }
     }
     if rem > 0 {
         writeln!(&mut stdout, "    ... ({} more commands)", rem)?;
@@ -76,14 +649,104 @@ pub fn print_session_brief(
     Ok(())
 }
 
+fn status_summary(summary: &SessionSummary) -> String {
+    let counts = [
+        (CommandStatus::Succeeded, "ok"),
+        (CommandStatus::Failed, "failed"),
+        (CommandStatus::Errored, "errored"),
+        (CommandStatus::Skipped, "skipped"),
+    ];
+    let parts: Vec<String> = counts
+        .iter()
+        .map(|(status, label)| {
+            (summary.records.iter().filter(|r| r.status == *status).count(), label)
+        })
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+    match parts.is_empty() {
+        true => "-".to_owned(),
+        false => parts.join(", "),
+    }
+}
+
+/// Renders one row per session with aligned columns: key, name, time, number of commands and
+/// a status summary. Numeric columns are right-aligned.
+pub fn format_session_table(
+    summaries: &[(usize, SessionSummary)],
+    relative_to: Option<DateTime<Utc>>,
+) -> String {
+    let header = ["#", "NAME", "TIME", "CMDS", "STATUS"].map(ToOwned::to_owned);
+    let mut rows = vec![header];
+    for (key, summary) in summaries {
+        let commands = summary.records.iter().filter(|r| r.status != CommandStatus::Annotation);
+        rows.push([
+            key.to_string(),
+            summary.name.clone(),
+            format_time(summary.recorded_at, relative_to),
+            commands.count().to_string(),
+            status_summary(summary),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in &rows {
+        let [key, name, time, commands, status] = row;
+        let line = format!(
+            "{:>w0$}  {:<w1$}  {:<w2$}  {:>w3$}  {}",
+            key,
+            name,
+            time,
+            commands,
+            status,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+        table.push_str(&line);
+        table.push('\n');
+    }
+    table
+}
+
+pub fn print_session_matches(
+    session: Session,
+    query: &SearchQuery,
+    mut stdout: impl Write,
+) -> std::io::Result<bool> {
+    let matches: Vec<_> = session
+        .records
+        .iter()
+        .filter_map(|record| query.match_record(record).map(|lines| (record, lines)))
+        .collect();
+
+    if matches.is_empty() {
+        return Ok(false);
+    }
+
+    writeln!(&mut stdout, "session {} ({})", session.name, format_datetime(session.recorded_at))?;
+    for (record, lines) in matches {
+        writeln!(&mut stdout, "$ {}", record.command)?;
+        write!(&mut stdout, "{}", context_window(&record.output_text(), &lines, query.context))?;
+    }
+
+    Ok(true)
+}
+
 #[cfg(test)]
 mod test {
     use chrono::TimeZone;
     use indoc::indoc;
     use rstest::rstest;
 
-    use crate::CommandRecord;
-
     use super::*;
 
     #[rstest]
@@ -94,6 +757,25 @@ mod test {
         assert_eq!(needs_newline(&s), expected);
     }
 
+    #[rstest]
+    #[case::plain("abc\n", false)]
+    #[case::color("\x1b[31mred\x1b[0m", true)]
+    #[case::bare_escape("\x1b", false)]
+    fn test_contains_ansi_escape(#[case] s: &str, #[case] expected: bool) {
+        assert_eq!(contains_ansi_escape(s), expected);
+    }
+
+    #[rstest]
+    #[case::plain("abc\n", "abc\n")]
+    #[case::csi("\x1b[31mred\x1b[0m", "red")]
+    #[case::csi_params("\x1b[1;32mbold\x1b[m green", "bold green")]
+    #[case::osc_bel("\x1b]0;title\x07text", "text")]
+    #[case::osc_st("\x1b]8;;http://example.com\x1b\\link\x1b]8;;\x1b\\", "link")]
+    #[case::trailing_escape("abc\x1b", "abc")]
+    fn test_strip_ansi(#[case] s: &str, #[case] expected: &str) {
+        assert_eq!(strip_ansi(s), expected);
+    }
+
     fn good_session() -> Session {
         Session {
             name: "session-name".into(),
@@ -103,18 +785,22 @@ mod test {
                     command: "echo hello".into(),
                     output: "hello\n".into(),
                     status: CommandStatus::Succeeded,
+                    ..Default::default()
                 },
                 CommandRecord {
                     command: "echo -n world".into(),
                     output: "world".into(),
                     status: CommandStatus::Succeeded,
+                    ..Default::default()
                 },
                 CommandRecord {
                     command: "echo \"hello, world!\"".into(),
                     output: "hello, world!\n".into(),
                     status: CommandStatus::Succeeded,
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         }
     }
 
@@ -127,18 +813,22 @@ mod test {
                     command: "echo hello".into(),
                     output: "hello\n".into(),
                     status: CommandStatus::Succeeded,
+                    ..Default::default()
                 },
                 CommandRecord {
                     command: "echo -n world".into(),
                     output: "world".into(),
                     status: CommandStatus::Failed,
+                    ..Default::default()
                 },
                 CommandRecord {
                     command: "echo \"hello, world!\"".into(),
                     output: "hello, world!\n".into(),
                     status: CommandStatus::Skipped,
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         }
     }
 
@@ -175,11 +865,121 @@ mod test {
     ) {
         let mut out = Vec::new();
         let mut err = Vec::new();
-        print_session(session, &mut out, &mut err).unwrap();
+        print_session(session, &PrintOptions::default(), &mut out, &mut err).unwrap();
         assert_eq!(String::from_utf8(out).unwrap(), expected_out);
         assert_eq!(String::from_utf8(err).unwrap(), expected_err);
     }
 
+    #[rstest]
+    #[case::good(good_session(), &["[1] $ echo hello", "[2] $ echo -n world", "[3] $ echo \"hello, world!\""])]
+    #[case::skipped(bad_session(), &["[1] $ echo hello", "[2] $ echo -n world"])]
+    #[case::annotation(annotated_session(), &["# greet first", "[1] $ echo hello"])]
+    #[case::errored(errored_session(), &["[1] $ cd gone", "[2] $ echo hello"])]
+    fn test_print_session_numbered(#[case] session: Session, #[case] expected: &[&str]) {
+        let options = PrintOptions { number: true, ..Default::default() };
+        let mut out = Vec::new();
+        print_session(session, &options, &mut out, Vec::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let commands: Vec<&str> =
+            out.lines().filter(|l| l.starts_with('[') || l.starts_with('#')).collect();
+        assert_eq!(commands, expected);
+    }
+
+    #[test]
+    fn test_print_session_no_banner() {
+        let options = PrintOptions { no_banner: true, ..Default::default() };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        print_session(good_session(), &options, &mut out, &mut err).unwrap();
+        assert_eq!(err, b"");
+
+        let mut expected = Vec::new();
+        print_session(good_session(), &PrintOptions::default(), &mut expected, Vec::new()).unwrap();
+        assert_eq!(out, expected);
+
+        let mut err = Vec::new();
+        print_session_script(good_session(), Default::default(), &options, Vec::new(), &mut err)
+            .unwrap();
+        assert_eq!(err, b"");
+    }
+
+    #[rstest]
+    #[case::head(Some(2), None, &["$ echo hello", "$ echo -n world"])]
+    #[case::tail(None, Some(2), &["$ echo -n world", "$ echo \"hello, world!\""])]
+    #[case::head_all(Some(5), None, &["$ echo hello", "$ echo -n world", "$ echo \"hello, world!\""])]
+    #[case::tail_zero(None, Some(0), &[])]
+    fn test_print_session_head_tail(
+        #[case] head: Option<usize>,
+        #[case] tail: Option<usize>,
+        #[case] expected: &[&str],
+    ) {
+        let options = PrintOptions { head, tail, ..Default::default() };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        print_session(good_session(), &options, &mut out, &mut err).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let commands: Vec<&str> = out.lines().filter(|l| l.starts_with("$ ")).collect();
+        assert_eq!(commands, expected);
+        assert_eq!(String::from_utf8(err).unwrap(), "session session-name (2020-01-02 03:04:05)\n");
+    }
+
+    #[test]
+    fn test_print_session_tail_numbered() {
+        let options = PrintOptions { tail: Some(1), number: true, ..Default::default() };
+        let mut out = Vec::new();
+        print_session(good_session(), &options, &mut out, Vec::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[3] $ echo \"hello, world!\"\nhello, world!\n"
+        );
+    }
+
+    #[test]
+    fn test_print_session_filtered() {
+        let mut session = good_session();
+        session.records[0].output = b"hello\nagain\n".to_vec();
+        let filter = |record: &CommandRecord| record.output_text().contains("hello");
+        let mut out = Vec::new();
+        print_session_filtered(
+            session,
+            &PrintOptions::default(),
+            Some(&filter),
+            &mut out,
+            Vec::new(),
+        )
+        .unwrap();
+        let expected = indoc! {r#"
+            $ echo hello
+            hello
+            again
+
+            $ echo "hello, world!"
+            hello, world!
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_session_grouped() {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        print_session_grouped(bad_session(), &PrintOptions::default(), &mut out, &mut err).unwrap();
+        let expected = indoc! {r#"
+            # Failed
+            $ echo -n world
+            world
+
+            # Succeeded
+            $ echo hello
+            hello
+
+            # Skipped
+            $ echo "hello, world!"
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(String::from_utf8(err).unwrap(), "session session-name (2020-01-02 03:04:05)\n");
+    }
+
     #[rstest]
     #[case::good(
         good_session(),
@@ -206,15 +1006,178 @@ mod test {
     ) {
         let mut out = Vec::new();
         let mut err = Vec::new();
-        print_session_script(session, &mut out, &mut err).unwrap();
+        print_session_script(
+            session,
+            Default::default(),
+            &PrintOptions::default(),
+            &mut out,
+            &mut err,
+        )
+        .unwrap();
         assert_eq!(String::from_utf8(out).unwrap(), expected_out);
         assert_eq!(String::from_utf8(err).unwrap(), expected_err);
     }
 
+    #[test]
+    fn test_print_session_script_with_shebang() {
+        let script = ScriptOptions { shebang: true, ..Default::default() };
+        let options = PrintOptions::default();
+        let mut out = Vec::new();
+        print_session_script(good_session(), script, &options, &mut out, Vec::new()).unwrap();
+        let expected = indoc! {r#"
+            #!/usr/bin/env bash
+            set -e
+            echo hello
+            echo -n world
+            echo "hello, world!"
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_session_script_only_succeeded() {
+        let script = ScriptOptions { only_succeeded: true, ..Default::default() };
+        let options = PrintOptions::default();
+        let mut out = Vec::new();
+        print_session_script(bad_session(), script, &options, &mut out, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "echo hello\n");
+    }
+
+    #[test]
+    fn test_render_config_plain() {
+        let on_tty = RenderConfig::resolve(true, true, Some(80), true);
+        let piped = RenderConfig::resolve(true, false, None, false);
+        assert_eq!(on_tty, piped);
+        assert_eq!(RenderConfig::resolve(false, false, None, false), piped);
+
+        let mut out_tty = Vec::new();
+        let mut out_piped = Vec::new();
+        print_session_brief(good_session(), 1, None, on_tty.width, None, false, &mut out_tty)
+            .unwrap();
+        print_session_brief(good_session(), 1, None, piped.width, None, false, &mut out_piped)
+            .unwrap();
+        assert_eq!(out_tty, out_piped);
+    }
+
+    #[test]
+    fn test_render_config_tty() {
+        let config = RenderConfig::resolve(false, true, Some(80), true);
+        assert_eq!(config, RenderConfig { tty: true, width: Some(80), color: true });
+    }
+
+    #[test]
+    fn test_print_session_omitted() {
+        let options = PrintOptions { omitted: 2, ..Default::default() };
+        let mut err = Vec::new();
+        print_session(good_session(), &options, Vec::new(), &mut err).unwrap();
+        let expected =
+            "session session-name (2020-01-02 03:04:05)\n(2 shared setup commands omitted)\n";
+        assert_eq!(String::from_utf8(err).unwrap(), expected);
+
+        let options = PrintOptions { no_banner: true, ..options };
+        let mut err = Vec::new();
+        print_session(good_session(), &options, Vec::new(), &mut err).unwrap();
+        assert_eq!(err, b"");
+    }
+
+    #[rstest]
+    fn test_color_mode_explicit(
+        #[values(ColorMode::Always, ColorMode::Never)] mode: ColorMode,
+        #[values(None, Some("1"), Some("0"))] clicolor_force: Option<&str>,
+        #[values(None, Some(""), Some("1"))] no_color: Option<&str>,
+        #[values(true, false)] tty: bool,
+    ) {
+        assert_eq!(mode.resolve(clicolor_force, no_color, tty), mode == ColorMode::Always);
+    }
+
+    #[rstest]
+    fn test_color_mode_clicolor_force(
+        #[values(Some("1"), Some("yes"))] clicolor_force: Option<&str>,
+        #[values(None, Some(""), Some("1"))] no_color: Option<&str>,
+        #[values(true, false)] tty: bool,
+    ) {
+        assert!(ColorMode::Auto.resolve(clicolor_force, no_color, tty));
+    }
+
+    #[rstest]
+    fn test_color_mode_no_color(
+        #[values(None, Some("0"))] clicolor_force: Option<&str>,
+        #[values(Some(""), Some("1"))] no_color: Option<&str>,
+        #[values(true, false)] tty: bool,
+    ) {
+        assert!(!ColorMode::Auto.resolve(clicolor_force, no_color, tty));
+    }
+
+    #[rstest]
+    fn test_color_mode_tty(
+        #[values(None, Some("0"))] clicolor_force: Option<&str>,
+        #[values(true, false)] tty: bool,
+    ) {
+        assert_eq!(ColorMode::Auto.resolve(clicolor_force, None, tty), tty);
+    }
+
+    #[rstest]
+    #[case::future(-30, "just now")]
+    #[case::just_now(59, "just now")]
+    #[case::one_minute(60, "1 minute ago")]
+    #[case::minutes(3599, "59 minutes ago")]
+    #[case::one_hour(3600, "1 hour ago")]
+    #[case::hours(86399, "23 hours ago")]
+    #[case::yesterday(86400, "yesterday")]
+    #[case::two_days(172800, "2 days ago")]
+    #[case::days(30 * 86400, "30 days ago")]
+    fn test_format_relative(#[case] secs: i64, #[case] expected: &str) {
+        let now = Utc.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap();
+        let dt = now - chrono::TimeDelta::seconds(secs);
+        assert_eq!(format_relative(dt, now), expected);
+    }
+
+    #[test]
+    fn test_print_session_brief_relative() {
+        let now = good_session().recorded_at + chrono::TimeDelta::minutes(3);
+        let mut out = Vec::new();
+        print_session_brief(good_session(), 1, Some(0), None, Some(now), false, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().next(), Some("1: session-name (3 minutes ago)"));
+    }
+
+    #[test]
+    fn test_print_session_script_relative() {
+        let now = good_session().recorded_at + chrono::TimeDelta::minutes(3);
+        let options = PrintOptions { relative_to: Some(now), ..Default::default() };
+        let mut err = Vec::new();
+        print_session_script(good_session(), Default::default(), &options, Vec::new(), &mut err)
+            .unwrap();
+        assert_eq!(String::from_utf8(err).unwrap(), "session session-name (3 minutes ago)\n");
+    }
+
+    #[rstest]
+    #[case::tty(true, Some((80, 24)))]
+    #[case::not_tty(false, None)]
+    fn test_dimensions_if_terminal(#[case] tty: bool, #[case] expected: Option<(u16, u16)>) {
+        let size = Some((Width(80), Height(24)));
+        assert_eq!(dimensions_if_terminal(tty, size), expected);
+        assert_eq!(dimensions_if_terminal(tty, None), None);
+    }
+
+    #[rstest]
+    #[case::short("abc", 5, "abc")]
+    #[case::exact("abcde", 5, "abcde")]
+    #[case::long("abcdef", 5, "ab...")]
+    #[case::narrow("abcdef", 2, "ab")]
+    #[case::zero("abcdef", 0, "")]
+    #[case::wide_exact("日本語", 6, "日本語")]
+    #[case::wide("日本語テキスト", 7, "日本...")]
+    #[case::wide_split("日本語", 5, "日...")]
+    fn test_truncate_line(#[case] line: &str, #[case] width: usize, #[case] expected: &str) {
+        assert_eq!(truncate_line(line, width), expected);
+    }
+
     #[rstest]
     #[case::good(
         good_session(),
         None,
+        None,
         indoc! {r#"
             123: session-name (2020-01-02 03:04:05)
                 $ echo hello
@@ -225,6 +1188,7 @@ mod test {
     #[case::bad(
         bad_session(),
         None,
+        None,
         indoc! {r#"
             123: session-name (2020-01-02 03:04:05)
                 $ echo hello
@@ -235,19 +1199,500 @@ mod test {
     #[case::max(
         good_session(),
         Some(1),
+        None,
         indoc! {r#"
             123: session-name (2020-01-02 03:04:05)
                 $ echo hello
                 ... (2 more commands)
         "#}.trim_start(),
     )]
+    #[case::truncated(
+        good_session(),
+        None,
+        Some(16),
+        indoc! {r#"
+            123: session-name (2020-01-02 03:04:05)
+                $ echo hello
+                $ echo -n...
+                $ echo "h...
+        "#}.trim_start(),
+    )]
     fn test_print_session_brief(
         #[case] session: Session,
         #[case] max: Option<usize>,
+        #[case] width: Option<usize>,
         #[case] expected: &str,
     ) {
         let mut out = Vec::new();
-        print_session_brief(session, 123, max, &mut out).unwrap();
+        print_session_brief(session, 123, max, width, None, false, &mut out).unwrap();
         assert_eq!(String::from_utf8(out).unwrap(), expected);
     }
+
+    #[test]
+    fn test_format_session_table() {
+        let short = Session { name: "s".into(), ..good_session() };
+        let mut long = bad_session();
+        long.records = (0..4).flat_map(|_| bad_session().records).collect();
+        let summaries = [(9, short.summary(false)), (10, long.summary(false))];
+        let expected = indoc! {"
+             #  NAME          TIME                 CMDS  STATUS
+             9  s             2020-01-02 03:04:05     3  3 ok
+            10  session-name  2020-01-02 03:04:05    12  4 ok, 4 failed, 4 skipped
+        "};
+        assert_eq!(format_session_table(&summaries, None), expected);
+    }
+
+    #[test]
+    fn test_print_session_tap() {
+        let mut session = bad_session();
+        session.records.insert(0, CommandRecord::annotation("build".into()));
+        session.records.push(CommandRecord::errored("make # all".into()));
+        let mut out = Vec::new();
+        print_session_tap(session, &mut out).unwrap();
+        let expected = indoc! {r#"
+            # build
+            ok 1 - echo hello
+            not ok 2 - echo -n world
+            ok 3 - echo "hello, world!" # SKIP
+            not ok 4 - make \# all
+            1..4
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_session_folded() {
+        let options = PrintOptions {
+            unfolded: Some(vec![CommandStatus::Failed]),
+            no_banner: true,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        print_session(bad_session(), &options, &mut out, std::io::sink()).unwrap();
+        let expected = indoc! {r#"
+            $ echo hello
+
+            $ echo -n world
+            world
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    fn described_session() -> Session {
+        let description = "fix the flaky build\nwith more details";
+        Session { description: Some(description.into()), ..good_session() }
+    }
+
+    #[rstest]
+    #[case::present(
+        described_session(),
+        "session session-name (2020-01-02 03:04:05)\nfix the flaky build\nwith more details\n"
+    )]
+    #[case::absent(good_session(), "session session-name (2020-01-02 03:04:05)\n")]
+    fn test_print_session_description(#[case] session: Session, #[case] expected_err: &str) {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        print_session(session, &PrintOptions::default(), &mut out, &mut err).unwrap();
+        assert_eq!(String::from_utf8(err).unwrap(), expected_err);
+    }
+
+    #[rstest]
+    #[case::normal(1.0, vec![PLAY_DELAY, PLAY_DELAY])]
+    #[case::fast(2.0, vec![PLAY_DELAY / 2, PLAY_DELAY / 2])]
+    #[case::instant(0.0, vec![])]
+    fn test_play_session(#[case] speed: f64, #[case] expected: Vec<Duration>) {
+        let mut sleeps = Vec::new();
+        let mut out = Vec::new();
+        let session = good_session();
+        let options = PrintOptions::default();
+        play_session(session, &options, speed, |d| sleeps.push(d), &mut out, Vec::new()).unwrap();
+        assert_eq!(sleeps, expected);
+
+        let mut printed = Vec::new();
+        print_session(good_session(), &options, &mut printed, Vec::new()).unwrap();
+        assert_eq!(out, printed);
+    }
+
+    #[rstest]
+    #[case::skipped(bad_session, false)]
+    #[case::annotated(annotated_session, false)]
+    #[case::numbered(bad_session, true)]
+    #[case::errored(errored_session, true)]
+    fn test_play_session_shown_records(#[case] session: fn() -> Session, #[case] number: bool) {
+        let options = PrintOptions { number, ..Default::default() };
+        let mut out = Vec::new();
+        play_session(session(), &options, 0.0, |_| {}, &mut out, Vec::new()).unwrap();
+
+        let mut printed = Vec::new();
+        print_session(session(), &options, &mut printed, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), String::from_utf8(printed).unwrap());
+    }
+
+    #[rstest]
+    #[case::short("hello world", 20, false, &["hello world"])]
+    #[case::words("the quick brown fox jumps", 10, false, &["the quick", "brown fox", "jumps"])]
+    #[case::long_word("abcdefghijkl", 5, false, &["abcde", "fghij", "kl"])]
+    #[case::hard("the quick brown fox", 10, true, &["the quick ", "brown fox"])]
+    #[case::empty("", 10, true, &[""])]
+    fn test_wrap_line(
+        #[case] line: &str,
+        #[case] width: usize,
+        #[case] hard: bool,
+        #[case] expected: &[&str],
+    ) {
+        assert_eq!(wrap_line(line, Wrap { width, hard }), expected);
+    }
+
+    #[test]
+    fn test_print_session_wrap() {
+        let session = Session {
+            records: vec![CommandRecord {
+                command: "cat".into(),
+                output: "one two three\n\nfour".into(),
+                status: CommandStatus::Succeeded,
+                ..Default::default()
+            }],
+            ..good_session()
+        };
+        let wrap = Some(Wrap { width: 8, hard: false });
+        let options = PrintOptions { wrap, ..Default::default() };
+        let mut out = Vec::new();
+        print_session(session, &options, &mut out, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "$ cat\none two\nthree\n\nfour\n");
+    }
+
+    #[test]
+    fn test_print_session_notes() {
+        let noted_at = Utc.with_ymd_and_hms(2020, 1, 3, 4, 5, 6).unwrap();
+        let session = Session {
+            records: vec![CommandRecord {
+                command: "true".into(),
+                output: Vec::new(),
+                status: CommandStatus::Succeeded,
+                ..Default::default()
+            }],
+            notes: vec![(noted_at, "broke because of X".into())],
+            ..good_session()
+        };
+        let mut out = Vec::new();
+        print_session(session, &PrintOptions::default(), &mut out, Vec::new()).unwrap();
+        let expected = "$ true\n\nNotes:\n[2020-01-03 04:05:06] broke because of X\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::short("fix the flaky build\nwith more details", "fix the flaky build")]
+    #[case::long(
+        "make the build reproducible across all of the supported platforms",
+        "make the build reproducible across al..."
+    )]
+    fn test_print_session_brief_description(#[case] description: &str, #[case] expected: &str) {
+        let session = Session { description: Some(description.into()), ..good_session() };
+        let mut out = Vec::new();
+        print_session_brief(session, 123, Some(0), None, None, false, &mut out).unwrap();
+        let expected = format!("123: session-name (2020-01-02 03:04:05) - {}\n", expected);
+        assert!(String::from_utf8(out).unwrap().starts_with(&expected));
+    }
+
+    #[rstest]
+    #[case::output(
+        "hello",
+        0,
+        indoc! {r#"
+            session session-name (2020-01-02 03:04:05)
+            $ echo hello
+            hello
+            $ echo "hello, world!"
+            hello, world!
+        "#},
+    )]
+    #[case::command_only(
+        "-n",
+        0,
+        indoc! {r#"
+            session session-name (2020-01-02 03:04:05)
+            $ echo -n world
+        "#},
+    )]
+    #[case::no_match("nothing", 0, "")]
+    fn test_print_session_matches(
+        #[case] pattern: &str,
+        #[case] context: usize,
+        #[case] expected: &str,
+    ) {
+        let query =
+            SearchQuery { pattern: pattern.into(), context, invert: false, command_only: false };
+        let mut out = Vec::new();
+        let found = print_session_matches(good_session(), &query, &mut out).unwrap();
+        assert_eq!(found, !expected.is_empty());
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::normal(
+        false,
+        indoc! {r#"
+            session session-name (2020-01-02 03:04:05)
+            $ echo hello
+            hello
+            $ echo "hello, world!"
+            hello, world!
+        "#},
+    )]
+    #[case::inverted(
+        true,
+        indoc! {r#"
+            session session-name (2020-01-02 03:04:05)
+            $ echo -n world
+        "#},
+    )]
+    fn test_print_session_matches_invert(#[case] invert: bool, #[case] expected: &str) {
+        let query =
+            SearchQuery { pattern: "hello".into(), context: 0, invert, command_only: false };
+        let mut out = Vec::new();
+        print_session_matches(bad_session(), &query, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::color(true, "\x1b[31mred\x1b[0m\n")]
+    #[case::no_color(false, "\x1b[31mred\n")]
+    fn test_print_session_ansi_reset(#[case] color: bool, #[case] expected_output: &str) {
+        let session = Session {
+            name: "session-name".into(),
+            recorded_at: Local.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap().into(),
+            records: vec![
+                CommandRecord {
+                    command: "printf '\\e[31mred'".into(),
+                    output: "\x1b[31mred".into(),
+                    status: CommandStatus::Succeeded,
+                    ..Default::default()
+                },
+                CommandRecord {
+                    command: "echo plain".into(),
+                    output: "plain\n".into(),
+                    status: CommandStatus::Succeeded,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let options = PrintOptions { color, ..Default::default() };
+        let mut out = Vec::new();
+        print_session(session, &options, &mut out, Vec::new()).unwrap();
+        let expected = format!("$ printf '\\e[31mred'\n{}\n$ echo plain\nplain\n", expected_output);
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_session_strip_ansi() {
+        let session = Session {
+            name: "session-name".into(),
+            recorded_at: Local.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap().into(),
+            records: vec![CommandRecord {
+                command: "color".into(),
+                output: "\x1b[31mred\x1b[0m".into(),
+                status: CommandStatus::Succeeded,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let options = PrintOptions { strip_ansi: true, ..Default::default() };
+        let mut out = Vec::new();
+        print_session(session, &options, &mut out, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "$ color\nred\n");
+    }
+
+    fn annotated_session() -> Session {
+        Session {
+            name: "session-name".into(),
+            recorded_at: Local.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap().into(),
+            records: vec![
+                CommandRecord::annotation("greet first".into()),
+                CommandRecord {
+                    command: "echo hello".into(),
+                    output: "hello\n".into(),
+                    status: CommandStatus::Succeeded,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn errored_session() -> Session {
+        Session {
+            name: "session-name".into(),
+            recorded_at: Local.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap().into(),
+            records: vec![
+                CommandRecord::errored("cd gone".into()),
+                CommandRecord {
+                    command: "echo hello".into(),
+                    output: "hello\n".into(),
+                    status: CommandStatus::Succeeded,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_print_session_errored() {
+        let mut out = Vec::new();
+        print_session(errored_session(), &PrintOptions::default(), &mut out, Vec::new()).unwrap();
+        let expected = indoc! {r#"
+            $ cd gone
+            (errored)
+
+            $ echo hello
+            hello
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_session_grouped_annotation() {
+        let mut out = Vec::new();
+        print_session_grouped(annotated_session(), &PrintOptions::default(), &mut out, Vec::new())
+            .unwrap();
+        let expected = indoc! {r#"
+            # Succeeded
+            $ echo hello
+            hello
+
+            # Notes
+            # greet first
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_session_annotation() {
+        let mut out = Vec::new();
+        print_session(annotated_session(), &PrintOptions::default(), &mut out, Vec::new()).unwrap();
+        let expected = indoc! {r#"
+            # greet first
+
+            $ echo hello
+            hello
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_print_session_script_annotation() {
+        let options = PrintOptions::default();
+        let mut out = Vec::new();
+        print_session_script(
+            annotated_session(),
+            Default::default(),
+            &options,
+            &mut out,
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "@note greet first\necho hello\n");
+    }
+
+    #[test]
+    fn test_print_session_work_dir() {
+        let record = |command: &str, work_dir: &str| CommandRecord {
+            command: command.into(),
+            output: Vec::new(),
+            status: CommandStatus::Succeeded,
+            work_dir: Some(work_dir.into()),
+            ..Default::default()
+        };
+        let session = Session {
+            name: "session-name".into(),
+            recorded_at: Local.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap().into(),
+            records: vec![
+                record("cd /tmp", "/tmp"),
+                record("ls", "/tmp"),
+                record("touch a", "/tmp"),
+                record("cd /", "/"),
+                record("pwd", "/"),
+            ],
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        print_session(session, &PrintOptions::default(), &mut out, Vec::new()).unwrap();
+        let expected = indoc! {r#"
+            $ cd /tmp
+
+            [/tmp]
+            $ ls
+
+            $ touch a
+
+            $ cd /
+
+            [/]
+            $ pwd
+        "#};
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        // no directory is shown when it never changes from where the session started
+        let session = Session {
+            records: vec![record("ls", "/tmp"), record("touch a", "/tmp"), record("ls", "/tmp")],
+            work_dir: Some("/tmp".into()),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        print_session(session, &PrintOptions::default(), &mut out, Vec::new()).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains('['));
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_print_session_html() {
+        let session = Session {
+            name: "session-name".into(),
+            recorded_at: Local.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap().into(),
+            records: vec![
+                CommandRecord {
+                    command: "echo '<script>alert(1)</script>'".into(),
+                    output: "<script>alert(1)</script>\n".into(),
+                    status: CommandStatus::Succeeded,
+                    ..Default::default()
+                },
+                CommandRecord {
+                    command: "false && true".into(),
+                    output: Vec::new(),
+                    status: CommandStatus::Failed,
+                    ..Default::default()
+                },
+                CommandRecord::errored("cd gone".into()),
+            ],
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        print_session_html(session, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(!out.contains("<script>"));
+        assert!(out.contains("<h1>session session-name (2020-01-02 03:04:05)</h1>"));
+        assert!(out.contains(indoc! {r#"
+            <pre class="succeeded"><span class="command">$ echo &#39;&lt;script&gt;alert(1)&lt;/script&gt;&#39;</span>
+            &lt;script&gt;alert(1)&lt;/script&gt;
+            </pre>
+        "#}));
+        assert!(out.contains(indoc! {r#"
+            <pre class="failed"><span class="command">$ false &amp;&amp; true</span>
+            </pre>
+        "#}));
+        assert!(out.contains(indoc! {r#"
+            <pre class="errored"><span class="command">$ cd gone</span>
+            (errored)
+            </pre>
+        "#}));
+    }
 }
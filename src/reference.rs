@@ -2,8 +2,36 @@
 pub enum ReferenceError {
     #[error("index out of range (ref = {reference})")]
     IndexOutOfRange { reference: String },
-    #[error("session not found (ref = {reference})")]
-    SessionNotFound { reference: String },
+    #[error("session not found (ref = {reference}){}", format_suggestions(.suggestions))]
+    SessionNotFound { reference: String, suggestions: Vec<String> },
+}
+
+/// Names further than this (in edit distance) from a missing reference are not suggested.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+const MAX_SUGGESTIONS: usize = 3;
+
+fn format_suggestions(suggestions: &[String]) -> String {
+    match suggestions.is_empty() {
+        true => String::new(),
+        false => format!("; did you mean {}?", suggestions.join(" or ")),
+    }
+}
+
+/// Returns the session names closest to `name`, nearest first.
+fn suggest_names(name: &str, session_names: &[String]) -> Vec<String> {
+    let mut candidates: Vec<(usize, &String)> = session_names
+        .iter()
+        .map(|candidate| (strsim::levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .collect();
+    // the sort is stable, so names at the same distance stay newest first
+    candidates.sort_by_key(|(distance, _)| *distance);
+    candidates.into_iter().take(MAX_SUGGESTIONS).map(|(_, name)| name.clone()).collect()
+}
+
+fn session_not_found(reference: &str, name: &str, session_names: &[String]) -> ReferenceError {
+    let suggestions = suggest_names(name, session_names);
+    ReferenceError::SessionNotFound { reference: reference.to_owned(), suggestions }
 }
 
 fn parse_index(s: &str) -> Option<usize> {
@@ -17,12 +45,33 @@ fn parse_index(s: &str) -> Option<usize> {
     }
 }
 
+/// Parses `@{name}~N` (or just `@{name}`) into the name and how many sessions older to go.
+fn parse_named_offset(s: &str) -> Option<(&str, usize)> {
+    let (name, offset) = s.strip_prefix("@{")?.split_once('}')?;
+    let offset = match offset {
+        "" => 0,
+        _ => offset.strip_prefix('~')?.parse().ok()?,
+    };
+    Some((name, offset))
+}
+
 pub fn resolve_reference(
     reference: impl AsRef<str>,
     session_names: &[String],
 ) -> Result<String, ReferenceError> {
     let reference = reference.as_ref();
-    if let Some(index) = parse_index(reference) {
+    if let Some((base, offset)) = parse_named_offset(reference) {
+        // names are sorted newest first, so older sessions come later
+        let index = session_names
+            .iter()
+            .position(|name| name == base)
+            .ok_or_else(|| session_not_found(reference, base, session_names))?;
+        let name = index
+            .checked_add(offset)
+            .and_then(|i| session_names.get(i))
+            .ok_or(ReferenceError::IndexOutOfRange { reference: reference.to_owned() })?;
+        Ok(name.clone())
+    } else if let Some(index) = parse_index(reference) {
         let name = session_names
             .get(index)
             .ok_or(ReferenceError::IndexOutOfRange { reference: reference.to_owned() })?;
@@ -32,7 +81,7 @@ pub fn resolve_reference(
         if found {
             Ok(reference.to_owned())
         } else {
-            Err(ReferenceError::SessionNotFound { reference: reference.to_owned() })
+            Err(session_not_found(reference, reference, session_names))
         }
     }
 }
@@ -41,7 +90,24 @@ pub fn resolve_references<I: IntoIterator<Item = S>, S: AsRef<str>>(
     references: I,
     session_names: &[String],
 ) -> Result<Vec<String>, ReferenceError> {
-    references.into_iter().map(|r| resolve_reference(r.as_ref(), session_names)).collect()
+    references
+        .into_iter()
+        .map(|r| {
+            let resolved = resolve_reference(r.as_ref(), session_names);
+            log::debug!("resolved reference {} to {:?}", r.as_ref(), resolved);
+            resolved
+        })
+        .collect()
+}
+
+/// Parses one reference per line, ignoring blank lines and `#` comments.
+pub fn parse_reference_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ToOwned::to_owned)
+        .collect()
 }
 
 #[cfg(test)]
@@ -57,15 +123,43 @@ mod test {
     #[case::one("@1", Some(0))]
     #[case::five("@5", Some(4))]
     #[case::invalid("@abc", None)]
+    #[case::named("@{abc}~1", None)]
     fn test_parse_index(#[case] s: &str, #[case] expected: Option<usize>) {
         assert_eq!(parse_index(s), expected);
     }
 
+    #[rstest]
+    #[case::offset("@{abc}~2", Some(("abc", 2)))]
+    #[case::no_offset("@{abc}", Some(("abc", 0)))]
+    #[case::invalid_offset("@{abc}~x", None)]
+    #[case::trailing("@{abc}x", None)]
+    #[case::unclosed("@{abc", None)]
+    fn test_parse_named_offset(#[case] s: &str, #[case] expected: Option<(&str, usize)>) {
+        assert_eq!(parse_named_offset(s), expected);
+    }
+
     #[rstest]
     #[case::by_index("@2", Ok("test2".into()))]
     #[case::index_out_of_range("@3", Err(IndexOutOfRange{ reference: "@3".into() }))]
     #[case::by_name("test1", Ok("test1".into()))]
-    #[case::name_not_found("test3", Err(SessionNotFound { reference: "test3".into() }))]
+    #[case::name_not_found(
+        "test3",
+        Err(SessionNotFound { reference: "test3".into(), suggestions: names(&["test1", "test2"]) })
+    )]
+    #[case::name_unrelated(
+        "deploy",
+        Err(SessionNotFound { reference: "deploy".into(), suggestions: Vec::new() })
+    )]
+    #[case::named_offset("@{test1}~1", Ok("test2".into()))]
+    #[case::named_without_offset("@{test2}", Ok("test2".into()))]
+    #[case::named_offset_out_of_range(
+        "@{test1}~2",
+        Err(IndexOutOfRange { reference: "@{test1}~2".into() })
+    )]
+    #[case::named_base_not_found(
+        "@{test3}~1",
+        Err(SessionNotFound { reference: "@{test3}~1".into(), suggestions: names(&["test1", "test2"]) })
+    )]
     fn test_resolve_reference(#[case] r: &str, #[case] expected: Result<String, ReferenceError>) {
         let names = vec!["test1".into(), "test2".into()];
         let actual = resolve_reference(r, &names);
@@ -89,4 +183,33 @@ mod test {
         let actual = resolve_references(r, &names);
         assert_eq!(actual, expected);
     }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[rstest]
+    #[case::typo("20190505-wxy", &["20190505-wxyz"])]
+    #[case::nearest_first("20200101-abcd", &["20200101-abcd", "20200102-abcd"])]
+    #[case::unrelated("deploy", &[])]
+    fn test_suggest_names(#[case] name: &str, #[case] expected: &[&str]) {
+        let session_names = names(&["20200102-abcd", "20200101-abcd", "20190505-wxyz"]);
+        assert_eq!(suggest_names(name, &session_names), names(expected));
+    }
+
+    #[test]
+    fn test_session_not_found_message() {
+        let session_names = names(&["20200102-abcd"]);
+        let err = resolve_reference("20200102-abce", &session_names).unwrap_err();
+        let expected = "session not found (ref = 20200102-abce); did you mean 20200102-abcd?";
+        assert_eq!(err.to_string(), expected);
+        let err = resolve_reference("deploy", &session_names).unwrap_err();
+        assert_eq!(err.to_string(), "session not found (ref = deploy)");
+    }
+
+    #[test]
+    fn test_parse_reference_list() {
+        let content = "@1\n# old sessions\n\n  test2  \n";
+        assert_eq!(parse_reference_list(content), vec!["@1", "test2"]);
+    }
 }
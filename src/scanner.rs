@@ -7,3 +7,44 @@ mod readline;
 pub use raw::*;
 #[cfg(feature = "readline")]
 pub use readline::*;
+
+pub const DEFAULT_PROMPT: &str = "==> ";
+
+/// Builds the interactive prompt, prefixed with the index of the upcoming command if given.
+pub fn build_prompt(index: Option<usize>) -> String {
+    match index {
+        Some(index) => format!("[{}] {}", index, DEFAULT_PROMPT),
+        None => DEFAULT_PROMPT.to_owned(),
+    }
+}
+
+/// Turns a (possibly pasted) multi-line input into a single command with `\n` line breaks.
+#[cfg_attr(not(feature = "readline"), allow(dead_code))]
+fn assemble_lines(input: &str) -> String {
+    let normalized = input.replace("\r\n", "\n").replace('\r', "\n");
+    normalized.trim_end_matches('\n').to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::single("echo hi", "echo hi")]
+    #[case::lf("for i in 1 2; do\n  echo $i\ndone", "for i in 1 2; do\n  echo $i\ndone")]
+    #[case::crlf("echo a &&\r\necho b\r\n", "echo a &&\necho b")]
+    #[case::cr("echo a &&\recho b\r", "echo a &&\necho b")]
+    #[case::trailing("echo hi\n\n", "echo hi")]
+    fn test_assemble_lines(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(assemble_lines(input), expected);
+    }
+
+    #[rstest]
+    #[case::plain(None, "==> ")]
+    #[case::indexed(Some(4), "[4] ==> ")]
+    fn test_build_prompt(#[case] index: Option<usize>, #[case] expected: &str) {
+        assert_eq!(build_prompt(index), expected);
+    }
+}
@@ -1,7 +1,9 @@
 use anyhow::{Context, Result};
 
-pub fn scan_line() -> Result<Option<String>> {
-    eprint!("==> ");
+/// Reads one line from STDIN as one command. Pasted text containing line breaks is therefore
+/// split into several commands; use the readline scanner for multi-line pastes.
+pub fn scan_line(prompt: &str) -> Result<Option<String>> {
+    eprint!("{}", prompt);
     let line = match std::io::stdin().lines().next() {
         Some(c) => Some(c.context("could not read command from STDIN")?),
         None => None,
@@ -1,18 +1,20 @@
 use anyhow::{Context, Result};
 use rustyline::error::ReadlineError;
-use rustyline::DefaultEditor;
+use rustyline::{Config, DefaultEditor};
 use std::cell::{OnceCell, RefCell};
 
 thread_local! {
-    static EDITOR: RefCell<OnceCell<DefaultEditor>> = RefCell::new(OnceCell::new());
+    static EDITOR: RefCell<OnceCell<DefaultEditor>> = const { RefCell::new(OnceCell::new()) };
 }
 
-fn scan_line_with_editor(editor: &mut DefaultEditor) -> Result<Option<String>> {
+fn scan_line_with_editor(editor: &mut DefaultEditor, prompt: &str) -> Result<Option<String>> {
     let history_path = crate::get_history_path()?;
 
     loop {
-        match editor.readline("==> ") {
+        match editor.readline(prompt) {
             Ok(line) => {
+                // a bracketed paste arrives as one line with embedded line breaks
+                let line = super::assemble_lines(&line);
                 editor.add_history_entry(&line).context("could not update line editor history")?;
                 let _ = editor.append_history(&history_path); // TODO: print warning message
                 return Ok(Some(line));
@@ -28,15 +30,17 @@ fn scan_line_with_editor(editor: &mut DefaultEditor) -> Result<Option<String>> {
     }
 }
 
-pub fn scan_line() -> Result<Option<String>> {
+pub fn scan_line(prompt: &str) -> Result<Option<String>> {
     EDITOR.with_borrow_mut(|cell| -> Result<Option<String>> {
         if cell.get().is_none() {
             let his = crate::get_history_path()?;
-            let mut editor = DefaultEditor::new().context("could not initialize line editor")?;
+            let config = Config::builder().bracketed_paste(true).build();
+            let mut editor =
+                DefaultEditor::with_config(config).context("could not initialize line editor")?;
             let _ = editor.load_history(&his); // TODO: print warning message
             cell.get_or_init(|| editor);
         }
         let editor = cell.get_mut().unwrap();
-        scan_line_with_editor(editor)
+        scan_line_with_editor(editor, prompt)
     })
 }
@@ -1,49 +1,282 @@
 use std::fs::File;
 use std::io::{stdin, BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
-pub fn read_script<B: BufRead>(reader: B) -> Result<Vec<String>> {
-    let is_empty = |line: &String| {
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptItem {
+    Command(String),
+    Parallel(Vec<String>),
+    Annotation(String),
+}
+
+const NOTE_PREFIX: &str = "@note ";
+
+pub fn format_annotation(text: &str) -> String {
+    format!("{}{}", NOTE_PREFIX, text)
+}
+
+fn parse_annotation(line: &str) -> Option<String> {
+    line.trim_start().strip_prefix(NOTE_PREFIX).map(ToOwned::to_owned)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Block {
+    Parallel,
+    /// Joins the enclosed lines with the separator into a single command.
+    Join(&'static str),
+}
+
+impl Block {
+    /// `@join` joins lines with newlines so they run as one shell script, while `@join &&`
+    /// chains them with `&&`.
+    fn parse(line: &str) -> Result<Option<Block>> {
         let line = line.trim();
-        line.is_empty() || line.starts_with("#!")
+        if line == "@parallel" {
+            return Ok(Some(Block::Parallel));
+        }
+        let separator = match line.strip_prefix("@join") {
+            Some("") => "\n",
+            Some(arg) if arg.starts_with(char::is_whitespace) => match arg.trim() {
+                "&&" => " && ",
+                arg => bail!("unknown `@join` separator `{}`", arg),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(Block::Join(separator)))
+    }
+
+    fn directive(self) -> &'static str {
+        match self {
+            Block::Parallel => "@parallel",
+            Block::Join(_) => "@join",
+        }
+    }
+
+    fn finish(self, lines: Vec<String>) -> Result<ScriptItem> {
+        match self {
+            Block::Parallel => Ok(ScriptItem::Parallel(lines)),
+            Block::Join(_) if lines.is_empty() => bail!("`@join` without any command"),
+            Block::Join(separator) => Ok(ScriptItem::Command(lines.join(separator))),
+        }
+    }
+}
+
+pub fn parse_script_items(lines: Vec<String>) -> Result<Vec<ScriptItem>> {
+    let mut items = Vec::new();
+    let mut group: Option<(Block, Vec<String>)> = None;
+
+    for line in lines {
+        if let Some(text) = parse_annotation(&line) {
+            if let Some((block, _)) = &group {
+                bail!("`@note` is not allowed inside `{}`", block.directive());
+            }
+            items.push(ScriptItem::Annotation(text));
+            continue;
+        }
+        if let Some(block) = Block::parse(&line)? {
+            if let Some((outer, _)) = &group {
+                bail!("`{}` is not allowed inside `{}`", block.directive(), outer.directive());
+            }
+            group = Some((block, Vec::new()));
+            continue;
+        }
+        match (line.trim(), group.as_mut()) {
+            ("@end", None) => bail!("`@end` without matching `@parallel` or `@join`"),
+            ("@end", Some(_)) => {
+                let (block, lines) = group.take().unwrap();
+                items.push(block.finish(lines)?);
+            }
+            (_, Some((_, commands))) => commands.push(line),
+            (_, None) => items.push(ScriptItem::Command(line)),
+        }
+    }
+
+    if let Some((block, _)) = group {
+        bail!("`{}` without matching `@end`", block.directive());
+    }
+    Ok(items)
+}
+
+/// Strips a `$ ` or `# ` prompt marker copied along with a command. Only the marker followed by
+/// exactly one space is stripped, so `$VAR=1` or `$(cmd)` stay as they are.
+pub fn strip_prompt(line: &str) -> String {
+    let trimmed = line.trim_start();
+    match trimmed.strip_prefix("$ ").or_else(|| trimmed.strip_prefix("# ")) {
+        Some(command) if !command.starts_with(' ') => command.to_owned(),
+        _ => line.to_owned(),
+    }
+}
+
+/// Detects a command that starts `scener run`, possibly after env assignments or in a later
+/// segment of a `;`, `&&`, `||` or `|` chain. Aliases and other indirection are not detected.
+pub fn invokes_scener_run(command: &str) -> bool {
+    command.split(['\n', ';', '&', '|']).any(|segment| {
+        let mut tokens = segment.split_whitespace().skip_while(|t| {
+            t.split_once('=').is_some_and(|(name, _)| {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            })
+        });
+        let program = tokens.next().map(|t| t.rsplit('/').next().unwrap_or(t));
+        program == Some("scener") && tokens.next() == Some("run")
+    })
+}
+
+fn is_empty_command(command: &str) -> bool {
+    let command = command.trim();
+    command.is_empty() || command.starts_with("#!")
+}
+
+pub fn read_script<B: BufRead>(reader: B) -> Result<Vec<String>> {
+    let is_empty = |line: &String| is_empty_command(line);
+
+    let strip_cr = |mut line: String| {
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        line
     };
 
     reader
         .lines()
+        .map(|line| line.map(strip_cr))
         .filter(|line| !line.as_ref().is_ok_and(is_empty))
         .map(|line| line.context("could not read line"))
         .collect()
 }
 
-pub fn read_script_from_stdin() -> Result<Vec<String>> {
-    read_script(BufReader::new(stdin())).context("could not read script from STDIN")
+/// Reads NUL-separated commands, which may span multiple lines. A trailing newline of each
+/// command is removed.
+pub fn read_script_nul<B: BufRead>(reader: B) -> Result<Vec<String>> {
+    let mut commands = Vec::new();
+    for chunk in reader.split(b'\0') {
+        let chunk = chunk.context("could not read command")?;
+        let command = String::from_utf8(chunk).context("command is not valid UTF-8")?;
+        let command = command.strip_suffix('\n').unwrap_or(&command);
+        let command = command.strip_suffix('\r').unwrap_or(command);
+        if !is_empty_command(command) {
+            commands.push(command.to_owned());
+        }
+    }
+    Ok(commands)
+}
+
+pub fn read_script_from_stdin(nul: bool) -> Result<Vec<String>> {
+    let reader = BufReader::new(stdin());
+    match nul {
+        true => read_script_nul(reader),
+        false => read_script(reader),
+    }
+    .context("could not read script from STDIN")
+}
+
+/// The commands of one script file, with its leading `#!` line (without the `#!`) kept aside.
+#[derive(Debug, PartialEq)]
+pub struct ScriptFile {
+    pub path: PathBuf,
+    pub shebang: Option<String>,
+    pub commands: Vec<String>,
+}
+
+impl ScriptFile {
+    /// Returns the commands to run: a single command running the whole file under the shebang
+    /// interpreter if `respect_shebang` is set and there is one, or the commands of the file.
+    pub fn into_commands(self, respect_shebang: bool) -> Vec<String> {
+        match self.shebang {
+            Some(interpreter) if respect_shebang => {
+                vec![format!("{} {}", interpreter, shell_quote(&self.path.to_string_lossy()))]
+            }
+            _ => self.commands,
+        }
+    }
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn read_shebang<B: BufRead>(reader: &mut B) -> std::io::Result<Option<String>> {
+    if !reader.fill_buf()?.starts_with(b"#!") {
+        return Ok(None);
+    }
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let interpreter = line.trim_start_matches("#!").trim();
+    Ok((!interpreter.is_empty()).then(|| interpreter.to_owned()))
+}
+
+fn read_script_from_file(path: &Path) -> Result<ScriptFile> {
+    let file = File::open(path)
+        .with_context(|| format!("could not open script file at {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let shebang = read_shebang(&mut reader)
+        .with_context(|| format!("could not read script from {}", path.display()))?;
+    let commands = read_script(reader)
+        .with_context(|| format!("could not read script from {}", path.display()))?;
+    // the interpreter may run after `--cwd` or a `cd` in an earlier command
+    let path = std::path::absolute(path)
+        .with_context(|| format!("could not resolve script path {}", path.display()))?;
+    Ok(ScriptFile { path, shebang, commands })
 }
 
 pub fn read_script_from_files<I: Iterator<Item = P>, P: AsRef<Path>>(
     paths: I,
-) -> Result<Vec<String>> {
-    let mut lines = Vec::new();
-    for path in paths.into_iter() {
+    ignore_missing: bool,
+) -> Result<Vec<ScriptFile>> {
+    read_script_from_files_with_stdin(paths, ignore_missing, BufReader::new(stdin()))
+}
+
+fn read_script_from_files_with_stdin<I: Iterator<Item = P>, P: AsRef<Path>, B: BufRead>(
+    paths: I,
+    ignore_missing: bool,
+    stdin: B,
+) -> Result<Vec<ScriptFile>> {
+    let is_stdin = |path: &Path| path == Path::new("-");
+
+    let paths: Vec<P> = paths.collect();
+    if paths.iter().filter(|p| is_stdin(p.as_ref())).count() > 1 {
+        bail!("`-` (STDIN) can be specified only once");
+    }
+
+    let mut stdin = Some(stdin);
+    let mut files = Vec::new();
+    let mut skipped = 0;
+    for path in paths.iter() {
         let path = path.as_ref();
-        let file = File::open(path)
-            .with_context(|| format!("could not open script file at {}", path.display()))?;
-        let script = read_script(BufReader::new(file))
-            .with_context(|| format!("could not read script from {}", path.display()))?;
-        lines.extend(script);
+        if is_stdin(path) {
+            // there is no file to hand to an interpreter, so a shebang is just stripped
+            let reader = stdin.take().expect("should not fail");
+            let commands = read_script(reader).context("could not read script from STDIN")?;
+            files.push(ScriptFile { path: path.to_owned(), shebang: None, commands });
+            continue;
+        }
+        match read_script_from_file(path) {
+            Ok(file) => files.push(file),
+            Err(err) if ignore_missing => {
+                eprintln!("warning: skipping script file: {:#}", err);
+                skipped += 1;
+            }
+            Err(err) => return Err(err),
+        }
     }
-    Ok(lines)
+
+    if !paths.is_empty() && skipped == paths.len() {
+        bail!("none of the script files could be read");
+    }
+    Ok(files)
 }
 
 #[cfg(test)]
 mod test {
 
+    use rstest::rstest;
     use tempfile::TempDir;
 
     use super::*;
     use std::fs::write;
     use std::io::{BufReader, Cursor};
+    use std::path::PathBuf;
 
     #[test]
     fn test_read_script() {
@@ -61,6 +294,104 @@ mod test {
         assert_eq!(expected, actual.ok());
     }
 
+    #[test]
+    fn test_read_script_nul() {
+        let content =
+            b"cat <<EOF\nhello\nEOF\n\0#!/bin/sh\0  \n\0for i in 1 2; do\n  echo $i\ndone\0";
+        let actual = read_script_nul(BufReader::new(Cursor::new(content))).unwrap();
+        assert_eq!(actual, vec!["cat <<EOF\nhello\nEOF", "for i in 1 2; do\n  echo $i\ndone"]);
+    }
+
+    #[test]
+    fn test_read_script_crlf() {
+        let content = b"abc\r\ndef \r\n\r\n";
+        let actual = read_script(BufReader::new(Cursor::new(content)));
+        let expected = Some(vec!["abc".to_owned(), "def ".to_owned()]);
+        assert_eq!(expected, actual.ok());
+    }
+
+    #[test]
+    fn test_parse_script_items() {
+        let lines = vec!["abc", "@parallel", "def", "  ghi", "@end", "@note some text", "jkl"];
+        let actual = parse_script_items(lines.into_iter().map(ToOwned::to_owned).collect());
+        let expected = Some(vec![
+            ScriptItem::Command("abc".into()),
+            ScriptItem::Parallel(vec!["def".into(), "  ghi".into()]),
+            ScriptItem::Annotation("some text".into()),
+            ScriptItem::Command("jkl".into()),
+        ]);
+        assert_eq!(expected, actual.ok());
+    }
+
+    #[rstest]
+    #[case::newline("@join", "abc\n  def")]
+    #[case::and("@join &&", "abc &&   def")]
+    fn test_parse_script_items_join(#[case] directive: &str, #[case] expected: &str) {
+        let lines = vec!["@note joined", directive, "abc", "  def", "@end", "ghi"];
+        let actual = parse_script_items(lines.into_iter().map(ToOwned::to_owned).collect());
+        let expected = Some(vec![
+            ScriptItem::Annotation("joined".into()),
+            ScriptItem::Command(expected.into()),
+            ScriptItem::Command("ghi".into()),
+        ]);
+        assert_eq!(expected, actual.ok());
+    }
+
+    #[rstest]
+    #[case::nested(&["@parallel", "@parallel", "@end", "@end"])]
+    #[case::unmatched_end(&["abc", "@end"])]
+    #[case::unterminated(&["@parallel", "abc"])]
+    #[case::note_in_parallel(&["@parallel", "@note abc", "@end"])]
+    #[case::join_in_parallel(&["@parallel", "@join", "@end", "@end"])]
+    #[case::unterminated_join(&["@join", "abc"])]
+    #[case::empty_join(&["@join", "@end"])]
+    #[case::unknown_separator(&["@join ;", "abc", "@end"])]
+    fn test_parse_script_items_invalid(#[case] lines: &[&str]) {
+        let actual = parse_script_items(lines.iter().map(|s| s.to_string()).collect());
+        assert!(actual.is_err());
+    }
+
+    #[rstest]
+    #[case::dollar("$ echo hi", "echo hi")]
+    #[case::hash("# apt install foo", "apt install foo")]
+    #[case::indented("  $ echo hi", "echo hi")]
+    #[case::variable("$VAR=1", "$VAR=1")]
+    #[case::substitution("$(make) all", "$(make) all")]
+    #[case::two_spaces("$  echo hi", "$  echo hi")]
+    #[case::bare("$", "$")]
+    #[case::plain("echo $ hi", "echo $ hi")]
+    fn test_strip_prompt(#[case] line: &str, #[case] expected: &str) {
+        assert_eq!(strip_prompt(line), expected);
+    }
+
+    #[rstest]
+    #[case::plain("scener run -f x.sh", true)]
+    #[case::path("/usr/local/bin/scener run", true)]
+    #[case::env("FOO=1 scener run", true)]
+    #[case::chained("cd dir && scener run", true)]
+    #[case::piped("echo ls | scener run", true)]
+    #[case::echo("echo scener run", false)]
+    #[case::other_subcommand("scener show @1", false)]
+    #[case::similar_name("scenery run", false)]
+    #[case::bare("scener", false)]
+    fn test_invokes_scener_run(#[case] command: &str, #[case] expected: bool) {
+        assert_eq!(invokes_scener_run(command), expected);
+    }
+
+    #[rstest]
+    #[case::note("@note hello", Some("hello"))]
+    #[case::indented("  @note hello", Some("hello"))]
+    #[case::bare("@note", None)]
+    #[case::command("echo @note hello", None)]
+    fn test_parse_annotation(#[case] line: &str, #[case] expected: Option<&str>) {
+        assert_eq!(parse_annotation(line).as_deref(), expected);
+    }
+
+    fn all_commands(files: Result<Vec<ScriptFile>>) -> Option<Vec<String>> {
+        let files = files.ok()?;
+        Some(files.into_iter().flat_map(|file| file.into_commands(false)).collect())
+    }
+
     #[test]
     fn test_read_script_from_files() {
         let temp_dir = TempDir::new().unwrap();
@@ -68,10 +399,93 @@ mod test {
         write(temp_path.join("file1"), b"abc\ndef\n").unwrap();
         write(temp_path.join("file2"), b"ghi\njkl\n").unwrap();
 
-        let actual =
-            read_script_from_files([temp_path.join("file1"), temp_path.join("file2")].iter());
+        let actual = read_script_from_files(
+            [temp_path.join("file1"), temp_path.join("file2")].iter(),
+            false,
+        );
+        let actual = all_commands(actual);
         let expected: Option<Vec<String>> =
             Some(vec!["abc", "def", "ghi", "jkl"].into_iter().map(ToOwned::to_owned).collect());
-        assert_eq!(expected, actual.ok());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_read_script_from_files_with_stdin() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        write(temp_path.join("file1"), b"abc\ndef\n").unwrap();
+        let stdin = BufReader::new(Cursor::new(b"ghi\njkl\n"));
+
+        let actual = read_script_from_files_with_stdin(
+            [temp_path.join("file1"), PathBuf::from("-")].iter(),
+            false,
+            stdin,
+        );
+        let actual = all_commands(actual);
+        let expected: Option<Vec<String>> =
+            Some(vec!["abc", "def", "ghi", "jkl"].into_iter().map(ToOwned::to_owned).collect());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_read_script_from_files_with_stdin_twice() {
+        let stdin = BufReader::new(Cursor::new(b"abc\n"));
+        let actual = read_script_from_files_with_stdin(["-", "-"].iter(), false, stdin);
+        assert!(actual.is_err());
+    }
+
+    #[rstest]
+    #[case::strict(false, None)]
+    #[case::ignore_missing(true, Some(vec!["abc".to_owned(), "def".to_owned()]))]
+    fn test_read_script_from_files_missing(
+        #[case] ignore_missing: bool,
+        #[case] expected: Option<Vec<String>>,
+    ) {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        write(temp_path.join("file1"), b"abc\ndef\n").unwrap();
+
+        let paths = [temp_path.join("file1"), temp_path.join("missing")];
+        let actual = read_script_from_files(paths.iter(), ignore_missing);
+        assert_eq!(expected, all_commands(actual));
+    }
+
+    #[test]
+    fn test_read_script_from_files_all_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        let paths = [temp_path.join("missing1"), temp_path.join("missing2")];
+        let actual = read_script_from_files(paths.iter(), true);
+        assert!(actual.is_err());
+    }
+
+    #[rstest]
+    #[case::respected(true, &["/usr/bin/env python3 'DIR/script.py'"])]
+    #[case::stripped(false, &["print('hello')", "print('world')"])]
+    fn test_read_script_from_files_shebang(#[case] respect: bool, #[case] expected: &[&str]) {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("script.py");
+        write(&path, b"#!/usr/bin/env python3\nprint('hello')\nprint('world')\n").unwrap();
+
+        let files = read_script_from_files([&path].iter(), false).unwrap();
+        assert_eq!(files[0].shebang.as_deref(), Some("/usr/bin/env python3"));
+        let commands: Vec<String> =
+            files.into_iter().flat_map(|file| file.into_commands(respect)).collect();
+        let dir = temp_dir.path().to_string_lossy();
+        let expected: Vec<String> = expected.iter().map(|c| c.replace("DIR", &dir)).collect();
+        assert_eq!(commands, expected);
+    }
+
+    #[test]
+    fn test_read_script_from_files_relative() {
+        let files = read_script_from_files(["Cargo.toml"].iter(), false).unwrap();
+        assert!(files[0].path.is_absolute());
+        assert_eq!(files[0].path, std::env::current_dir().unwrap().join("Cargo.toml"));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("it's here"), r"'it'\''s here'");
     }
 }
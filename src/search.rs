@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+use crate::CommandRecord;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub pattern: String,
+    pub context: usize,
+    pub invert: bool,
+    pub command_only: bool,
+}
+
+pub fn find_matching_lines(output: &str, pattern: &str) -> Vec<usize> {
+    output.lines().enumerate().filter(|(_, line)| line.contains(pattern)).map(|(i, _)| i).collect()
+}
+
+pub fn context_window(output: &str, line_indices: &[usize], context: usize) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+
+    let mut indices = line_indices.to_vec();
+    indices.sort();
+
+    let mut windows: Vec<Range<usize>> = Vec::new();
+    for index in indices.into_iter().filter(|&i| i < lines.len()) {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        match windows.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => windows.push(start..end),
+        }
+    }
+
+    let mut snippet = String::new();
+    for (n, window) in windows.into_iter().enumerate() {
+        if n > 0 {
+            snippet.push_str("--\n");
+        }
+        for line in &lines[window] {
+            snippet.push_str(line);
+            snippet.push('\n');
+        }
+    }
+    snippet
+}
+
+impl SearchQuery {
+    /// A record matches when its command or output contains the pattern (only the command
+    /// with `command_only`). With `invert`, an executed record matches when it would not
+    /// match otherwise. Returns the matching output lines of a matching record.
+    pub fn match_record(&self, record: &CommandRecord) -> Option<Vec<usize>> {
+        let lines = match self.command_only {
+            true => Vec::new(),
+            false => find_matching_lines(&record.output_text(), &self.pattern),
+        };
+        let matched = record.command.contains(&self.pattern) || !lines.is_empty();
+        match self.invert {
+            false => matched.then_some(lines),
+            true => (!matched && record.status.is_executed()).then(Vec::new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use rstest::rstest;
+
+    use crate::CommandStatus;
+
+    use super::*;
+
+    const OUTPUT: &str = "l0\nl1\nl2\nl3\nl4\nl5\nl6\nl7\n";
+
+    #[test]
+    fn test_find_matching_lines() {
+        let actual = find_matching_lines("abc\ndef\nxabcx\n", "abc");
+        assert_eq!(actual, vec![0, 2]);
+    }
+
+    #[rstest]
+    #[case::no_context(&[3], 0, "l3\n")]
+    #[case::start(&[0], 2, "l0\nl1\nl2\n")]
+    #[case::end(&[7], 2, "l5\nl6\nl7\n")]
+    #[case::overlapping(&[2, 4], 1, "l1\nl2\nl3\nl4\nl5\n")]
+    #[case::adjacent(&[1, 4], 1, "l0\nl1\nl2\nl3\nl4\nl5\n")]
+    #[case::separate(&[0, 7], 1, indoc! {"
+        l0
+        l1
+        --
+        l6
+        l7
+    "})]
+    #[case::unsorted(&[7, 0], 0, "l0\n--\nl7\n")]
+    #[case::empty(&[], 2, "")]
+    fn test_context_window(
+        #[case] indices: &[usize],
+        #[case] context: usize,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(context_window(OUTPUT, indices, context), expected);
+    }
+
+    fn query(pattern: &str, invert: bool, command_only: bool) -> SearchQuery {
+        SearchQuery { pattern: pattern.into(), context: 0, invert, command_only }
+    }
+
+    #[rstest]
+    #[case::output(query("hello", false, false), Some(vec![0]))]
+    #[case::command(query("echo", false, false), Some(vec![]))]
+    #[case::none(query("world", false, false), None)]
+    #[case::command_only(query("hello", false, true), None)]
+    #[case::command_only_match(query("echo", false, true), Some(vec![]))]
+    #[case::invert(query("world", true, false), Some(vec![]))]
+    #[case::invert_output(query("hello", true, false), None)]
+    #[case::invert_command_only(query("hello", true, true), Some(vec![]))]
+    #[case::invert_command_only_match(query("echo", true, true), None)]
+    fn test_match_record(#[case] query: SearchQuery, #[case] expected: Option<Vec<usize>>) {
+        let record = CommandRecord {
+            command: "echo hi".into(),
+            output: "hello\nbye\n".into(),
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        };
+        assert_eq!(query.match_record(&record), expected);
+    }
+
+    #[test]
+    fn test_match_record_invert_skipped() {
+        let record = CommandRecord {
+            command: "echo hi".into(),
+            output: Vec::new(),
+            status: CommandStatus::Skipped,
+            ..Default::default()
+        };
+        assert_eq!(query("world", true, false).match_record(&record), None);
+    }
+}
@@ -1,39 +1,167 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
 use std::fs::{create_dir_all, remove_file, File};
-use std::path::Path;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
+use anyhow::{bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::ValueEnum;
 use rand::seq::SliceRandom;
+use serde::de::{Deserializer as _, IgnoredAny, MapAccess, Visitor};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::get_session_dir;
+use crate::{get_session_dir, truncate_line};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "snake_case")]
 pub enum CommandStatus {
+    #[default]
     Succeeded,
     Failed,
     Skipped,
+    #[value(skip)]
+    Annotation,
+    /// The command could not be executed at all (e.g. bash failed to start)
+    Errored,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// Stores output as `{"encoding": "utf8", "data": ...}`, or base64-encoded when it is not
+/// valid UTF-8. Plain strings written by older versions are read as UTF-8 output.
+mod output_encoding {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{decode_base64, encode_base64};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Encoding {
+        Utf8,
+        Base64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Encoded {
+        encoding: Encoding,
+        data: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Plain(String),
+        Encoded(Encoded),
+    }
+
+    pub fn serialize<S: Serializer>(output: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = match std::str::from_utf8(output) {
+            Ok(text) => Encoded { encoding: Encoding::Utf8, data: text.to_owned() },
+            Err(_) => Encoded { encoding: Encoding::Base64, data: encode_base64(output) },
+        };
+        encoded.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match Stored::deserialize(deserializer)? {
+            Stored::Plain(data) | Stored::Encoded(Encoded { encoding: Encoding::Utf8, data }) => {
+                Ok(data.into_bytes())
+            }
+            Stored::Encoded(Encoded { encoding: Encoding::Base64, data }) => {
+                decode_base64(&data).map_err(D::Error::custom)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
 pub struct CommandRecord {
     pub command: String,
-    pub output: String,
+    #[serde(with = "output_encoding")]
+    pub output: Vec<u8>,
     pub status: CommandStatus,
+    #[serde(default)]
+    pub work_dir: Option<String>,
+    /// File holding the output when it was spilled out of the session (see `spill_output`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_ref: Option<PathBuf>,
+    /// Hex-encoded SHA-256 of the output, recorded with `run --hash-output`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_hash: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// Spilled outputs live in their own files, so only the reference to them is written.
+impl Serialize for CommandRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Stored<'a> {
+            command: &'a str,
+            #[serde(serialize_with = "output_encoding::serialize")]
+            output: &'a [u8],
+            status: CommandStatus,
+            work_dir: &'a Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            output_ref: &'a Option<PathBuf>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            output_hash: &'a Option<String>,
+        }
+
+        let output = match self.output_ref {
+            Some(_) => &[],
+            None => self.output.as_slice(),
+        };
+        Stored {
+            command: &self.command,
+            output,
+            status: self.status,
+            work_dir: &self.work_dir,
+            output_ref: &self.output_ref,
+            output_hash: &self.output_hash,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SessionSource {
+    File(Vec<PathBuf>),
+    Url(String),
+    Session(Vec<String>),
+    Command,
+    Stdin,
+    Interactive,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Session {
     pub name: String,
     pub recorded_at: DateTime<Utc>,
     pub records: Vec<CommandRecord>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub source: Option<SessionSource>,
+    #[serde(default)]
+    pub notes: Vec<(DateTime<Utc>, String)>,
+    /// Terminal (width, height) at record time, if STDOUT was a terminal
+    #[serde(default)]
+    pub tty_size: Option<(u16, u16)>,
+    /// Working directory the first command ran in
+    #[serde(default)]
+    pub work_dir: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct CommandRecordSummary {
     pub command: String,
     pub status: CommandStatus,
+    #[serde(default)]
+    pub output_preview: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -43,11 +171,105 @@ pub struct SessionSummary {
     pub records: Vec<CommandRecordSummary>,
 }
 
-fn generate_session_key(now: DateTime<Utc>) -> String {
-    let now = now.format("%Y%m%d%H%M%S%3f");
+const OUTPUT_PREVIEW_WIDTH: usize = 40;
+
+/// Outputs larger than this are written to separate files by `run --spill-output`.
+pub const SPILL_THRESHOLD: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionKeyFormat {
+    pub timestamp: String,
+    pub suffix_len: usize,
+}
+
+impl Default for SessionKeyFormat {
+    fn default() -> Self {
+        SessionKeyFormat { timestamp: "%Y%m%d%H%M%S%3f".into(), suffix_len: 8 }
+    }
+}
+
+/// Checks that `format` is a strftime format whose output can be used in session names.
+fn validate_timestamp_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        bail!("{:?} is not a valid strftime format", format);
+    }
+    let sample = DateTime::<Utc>::default().format(format).to_string();
+    validate_session_name(&sample)
+        .with_context(|| format!("{:?} does not produce valid session names", format))
+}
+
+/// Checks that formatted timestamps sort in chronological order, by comparing instants that
+/// differ in each field from milliseconds up to years, and an instant where every field rolls
+/// over at once. `format` must be valid (see `validate_timestamp_format`).
+fn is_chronological_format(format: &str) -> bool {
+    let last: DateTime<Utc> =
+        DateTime::parse_from_rfc3339("2001-12-31T23:59:59.999Z").unwrap().into();
+    let next = last + TimeDelta::milliseconds(1);
+    if last.format(format).to_string() >= next.format(format).to_string() {
+        return false;
+    }
+
+    let base: DateTime<Utc> =
+        DateTime::parse_from_rfc3339("2001-02-03T04:05:06.007Z").unwrap().into();
+    let steps = [
+        TimeDelta::milliseconds(1),
+        TimeDelta::seconds(1),
+        TimeDelta::minutes(1),
+        TimeDelta::hours(1),
+        TimeDelta::days(1),
+        TimeDelta::days(31),
+        TimeDelta::days(366),
+    ];
+    let base_key = base.format(format).to_string();
+    steps.iter().all(|&step| base_key < (base + step).format(format).to_string())
+}
+
+/// Longer suffixes make session names unwieldy without making them any more unique.
+const MAX_SUFFIX_LEN: usize = 32;
+
+fn parse_suffix_len(len: &str) -> Result<usize> {
+    let len: usize = len.parse().with_context(|| format!("{:?} is not a number", len))?;
+    if len > MAX_SUFFIX_LEN {
+        bail!("{} is longer than {}", len, MAX_SUFFIX_LEN);
+    }
+    Ok(len)
+}
+
+impl SessionKeyFormat {
+    /// Reads `SCENER_NAME_FORMAT` and `SCENER_NAME_SUFFIX_LEN`, falling back to the defaults
+    /// with a warning when a value is invalid.
+    pub fn from_env() -> Self {
+        let mut key_format = SessionKeyFormat::default();
+        if let Ok(format) = std::env::var("SCENER_NAME_FORMAT") {
+            if let Err(err) = validate_timestamp_format(&format) {
+                eprintln!("warning: SCENER_NAME_FORMAT is ignored: {:#}", err);
+            } else {
+                if !is_chronological_format(&format) {
+                    eprintln!("warning: SCENER_NAME_FORMAT may not sort sessions chronologically");
+                }
+                key_format.timestamp = format;
+            }
+        }
+        if let Ok(len) = std::env::var("SCENER_NAME_SUFFIX_LEN") {
+            match parse_suffix_len(&len) {
+                Ok(len) => key_format.suffix_len = len,
+                Err(err) => eprintln!("warning: SCENER_NAME_SUFFIX_LEN is ignored: {:#}", err),
+            }
+        }
+        key_format
+    }
+}
+
+fn generate_session_key(now: DateTime<Utc>, key_format: &SessionKeyFormat) -> String {
+    let now = now.format(&key_format.timestamp);
+    if key_format.suffix_len == 0 {
+        return now.to_string();
+    }
     let charset = b"0123456789abcdef";
     let mut rng = rand::thread_rng();
-    let suffix = (0..8).flat_map(|_| charset.choose(&mut rng).copied().into_iter()).collect();
+    let suffix = (0..key_format.suffix_len)
+        .flat_map(|_| charset.choose(&mut rng).copied().into_iter())
+        .collect();
     let suffix_string = String::from_utf8(suffix).expect("should not fail");
     format!("{}-{}", now, suffix_string)
 }
@@ -58,6 +280,8 @@ impl CommandStatus {
             CommandStatus::Succeeded => true,
             CommandStatus::Failed => true,
             CommandStatus::Skipped => false,
+            CommandStatus::Annotation => false,
+            CommandStatus::Errored => false,
         }
     }
     pub fn is_succeeded(&self) -> bool {
@@ -65,31 +289,189 @@ impl CommandStatus {
             CommandStatus::Succeeded => true,
             CommandStatus::Failed => false,
             CommandStatus::Skipped => false,
+            CommandStatus::Annotation => false,
+            CommandStatus::Errored => false,
+        }
+    }
+    pub fn is_failed(&self) -> bool {
+        match self {
+            CommandStatus::Succeeded => false,
+            CommandStatus::Failed => true,
+            CommandStatus::Skipped => false,
+            CommandStatus::Annotation => false,
+            CommandStatus::Errored => false,
+        }
+    }
+    /// Tells whether the record is shown as a command, having either run or failed to start.
+    pub fn is_attempted(&self) -> bool {
+        self.is_executed() || *self == CommandStatus::Errored
+    }
+    /// Tells whether the command should be retried, having either failed or not run at all.
+    pub fn is_failed_or_errored(&self) -> bool {
+        matches!(self, CommandStatus::Failed | CommandStatus::Errored)
+    }
+}
+
+impl CommandRecord {
+    /// Returns the output as text, replacing invalid UTF-8 sequences.
+    pub fn output_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.output)
+    }
+
+    /// Returns the first line of the output truncated to a fixed width, if there is output.
+    pub fn output_preview(&self) -> Option<String> {
+        let text = self.output_text();
+        let line = text.lines().next()?;
+        Some(truncate_line(line, OUTPUT_PREVIEW_WIDTH))
+    }
+
+    pub fn annotation(text: String) -> Self {
+        CommandRecord { command: text, status: CommandStatus::Annotation, ..Default::default() }
+    }
+
+    pub fn errored(command: String) -> Self {
+        CommandRecord { command, status: CommandStatus::Errored, ..Default::default() }
+    }
+
+    /// Moves the output into the file at `path`, keeping only a reference to it.
+    pub fn spill_output(&mut self, path: &Path) -> Result<()> {
+        std::fs::write(path, &self.output)
+            .with_context(|| format!("could not write output to {}", path.display()))?;
+        self.output = Vec::new();
+        self.output_ref = Some(path.to_owned());
+        Ok(())
+    }
+
+    /// Returns the hex-encoded SHA-256 of the output.
+    pub fn compute_output_hash(&self) -> String {
+        Sha256::digest(&self.output).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Reads spilled output back into `output`.
+    pub fn load_spilled_output(&mut self) -> Result<()> {
+        if let Some(path) = &self.output_ref {
+            self.output = std::fs::read(path)
+                .with_context(|| format!("could not read spilled output at {}", path.display()))?;
         }
+        Ok(())
     }
 }
 
 impl Session {
-    pub fn new(recorded_at: DateTime<Utc>, records: Vec<CommandRecord>) -> Self {
-        Session { name: generate_session_key(recorded_at), recorded_at, records }
+    /// Spills every output larger than `threshold` bytes to `<dir>/<session name>-<index>.out`,
+    /// where `index` is the 1-based position of the record.
+    pub fn spill_outputs(&mut self, dir: &Path, threshold: usize) -> Result<()> {
+        let large = |r: &CommandRecord| r.output.len() > threshold;
+        if !self.records.iter().any(large) {
+            return Ok(());
+        }
+        create_dir_all(dir)
+            .with_context(|| format!("could not create directory {}", dir.display()))?;
+        for (index, record) in self.records.iter_mut().enumerate().filter(|(_, r)| large(r)) {
+            record.spill_output(&dir.join(format!("{}-{}.out", self.name, index + 1)))?;
+        }
+        Ok(())
+    }
+
+    pub fn load_spilled_outputs(&mut self) -> Result<()> {
+        self.records.iter_mut().try_for_each(CommandRecord::load_spilled_output)
+    }
+
+    /// Drops the references to spilled outputs, so that the loaded outputs are written into the
+    /// session itself.
+    pub fn inline_spilled_outputs(&mut self) {
+        for record in &mut self.records {
+            record.output_ref = None;
+        }
+    }
+
+    pub fn new(
+        recorded_at: DateTime<Utc>,
+        records: Vec<CommandRecord>,
+        key_format: &SessionKeyFormat,
+    ) -> Self {
+        let version = Some(env!("CARGO_PKG_VERSION").to_owned());
+        let name = generate_session_key(recorded_at, key_format);
+        Session { name, recorded_at, records, version, ..Default::default() }
     }
-    pub fn summary(&self) -> SessionSummary {
+    /// Appends a counter to the name while a session with the same name exists in `dir`, which
+    /// happens when names have no random suffix.
+    pub fn make_name_unique_in(&mut self, dir: impl AsRef<Path>) {
+        let dir = dir.as_ref();
+        let base = self.name.clone();
+        let mut count = 1;
+        while session_path_in_dir(dir, &self.name).exists() {
+            count += 1;
+            self.name = format!("{}-{}", base, count);
+        }
+    }
+    pub fn regenerate_name(&mut self, key_format: &SessionKeyFormat) {
+        self.name = generate_session_key(self.recorded_at, key_format);
+    }
+    /// Summarizes the session; `with_preview` also keeps a short preview of each output.
+    pub fn summary(&self, with_preview: bool) -> SessionSummary {
         let records = self
             .records
             .iter()
-            .map(|r| CommandRecordSummary { command: r.command.clone(), status: r.status })
+            .map(|r| CommandRecordSummary {
+                command: r.command.clone(),
+                status: r.status,
+                output_preview: with_preview.then(|| r.output_preview()).flatten(),
+            })
             .collect();
         SessionSummary { name: self.name.clone(), recorded_at: self.recorded_at, records }
     }
 }
 
+pub fn merge_sessions(
+    sessions: Vec<Session>,
+    name: Option<String>,
+    newest: bool,
+    key_format: &SessionKeyFormat,
+) -> Result<Session> {
+    let times = sessions.iter().map(|s| s.recorded_at);
+    let recorded_at = match newest {
+        true => times.max(),
+        false => times.min(),
+    };
+    let recorded_at = recorded_at.context("no sessions to merge")?;
+
+    let records = sessions.into_iter().flat_map(|s| s.records).collect();
+    let mut merged = Session::new(recorded_at, records, key_format);
+    if let Some(name) = name {
+        merged.name = name;
+    }
+    Ok(merged)
+}
+
+const DEFAULT_WARN_SIZE: usize = 1024 * 1024;
+
+/// Reads the size in bytes above which a session is reported as oversized from
+/// `SCENER_WARN_SIZE`.
+fn warn_size() -> usize {
+    std::env::var("SCENER_WARN_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_WARN_SIZE)
+}
+
+fn size_warning(session: &Session, size: usize, threshold: usize) -> Option<String> {
+    (size > threshold).then(|| {
+        format!(
+            "warning: session {} is {} bytes, which slows down listing sessions; \
+             consider capturing less output",
+            session.name, size
+        )
+    })
+}
+
 fn write_session_to_file(path: impl AsRef<Path>, session: &Session) -> Result<()> {
     let path = path.as_ref();
     if let Some(parent) = path.parent() {
         create_dir_all(parent).context("could not create parent directory")?;
     }
-    let file = File::create(path).context("could not create file")?;
-    serde_json::to_writer(file, session).context("could not write to file")
+    let content = serde_json::to_vec(session).context("could not serialize session")?;
+    if let Some(warning) = size_warning(session, content.len(), warn_size()) {
+        eprintln!("{}", warning);
+    }
+    std::fs::write(path, content).context("could not write to file")
 }
 
 fn read_session_from_file(path: impl AsRef<Path>) -> Result<Session> {
@@ -98,7 +480,47 @@ fn read_session_from_file(path: impl AsRef<Path>) -> Result<Session> {
     serde_json::from_reader(file).context("could not parse file")
 }
 
-fn list_session_names_from_dir(dir: impl AsRef<Path>) -> Result<Vec<String>> {
+/// Picks `recorded_at` out of a session object and stops reading there, so that listing does not
+/// parse the records of every session.
+struct RecordedAtVisitor<'a>(&'a mut Option<DateTime<Utc>>);
+
+impl<'de> Visitor<'de> for RecordedAtVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a session object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "recorded_at" {
+                *self.0 = Some(map.next_value()?);
+                return Ok(());
+            }
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(())
+    }
+}
+
+fn read_recorded_at(path: impl AsRef<Path>) -> Result<DateTime<Utc>> {
+    let file = BufReader::new(File::open(path).context("could not open file")?);
+    let mut recorded_at = None;
+    // stopping in the middle of the object makes the deserializer report an error afterwards
+    let result = serde_json::Deserializer::from_reader(file)
+        .deserialize_map(RecordedAtVisitor(&mut recorded_at));
+    match recorded_at {
+        Some(recorded_at) => Ok(recorded_at),
+        None => {
+            result.context("could not parse file")?;
+            bail!("recorded_at is missing")
+        }
+    }
+}
+
+/// Returns session names, most recently recorded first. Sessions whose timestamp cannot be read
+/// are listed last, and ties are broken by name.
+pub fn list_session_names_from_dir(dir: impl AsRef<Path>) -> Result<Vec<String>> {
     let dir = dir.as_ref();
 
     let mut sessions = Vec::new();
@@ -108,7 +530,7 @@ fn list_session_names_from_dir(dir: impl AsRef<Path>) -> Result<Vec<String>> {
             Ok(entry) => entry,
             _ => continue,
         };
-        let is_file = entry.file_type().map_or(false, |typ| typ.is_file());
+        let is_file = entry.file_type().is_ok_and(|typ| typ.is_file());
         if !is_file {
             continue;
         }
@@ -120,24 +542,77 @@ fn list_session_names_from_dir(dir: impl AsRef<Path>) -> Result<Vec<String>> {
         }
     }
 
-    sessions.sort();
-    sessions.reverse();
+    sessions.sort_by_cached_key(|name| {
+        let recorded_at = read_recorded_at(session_path_in_dir(dir, name)).ok();
+        Reverse((recorded_at, name.clone()))
+    });
 
     Ok(sessions)
 }
 
-pub fn write_session(session: &Session) -> Result<()> {
-    let session_dir = get_session_dir().context("could not locate session data directory")?;
-    let path = session_dir.join(format!("{}.json", session.name));
+/// Checks that `name` stays inside the session directory when used as a file name, since names
+/// read from archives or session files are not trusted.
+pub fn validate_session_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains("..") || name.contains(['/', '\\', '\0']) {
+        bail!("invalid session name {:?}", name);
+    }
+    Ok(())
+}
+
+pub fn session_path_in_dir(dir: impl AsRef<Path>, name: &str) -> PathBuf {
+    dir.as_ref().join(format!("{}.json", name))
+}
+
+pub fn write_session_to_dir(dir: impl AsRef<Path>, session: &Session) -> Result<()> {
+    let path = session_path_in_dir(dir, &session.name);
+    log::debug!("writing session {} to {}", session.name, path.display());
     write_session_to_file(&path, session)
         .with_context(|| format!("could not write session data into {}", path.display()))
 }
 
+/// Reads the session, along with its spilled outputs.
+pub fn read_session_from_dir(dir: impl AsRef<Path>, name: &str) -> Result<Session> {
+    let path = session_path_in_dir(dir, name);
+    log::debug!("reading session {} from {}", name, path.display());
+    let mut session = read_session_from_file(&path)
+        .with_context(|| format!("could not read session data from {}", path.display()))?;
+    session.load_spilled_outputs()?;
+    Ok(session)
+}
+
+/// Removes the session, along with its spilled outputs.
+pub fn remove_session_from_dir(dir: impl AsRef<Path>, name: &str) -> Result<()> {
+    let path = session_path_in_dir(dir, name);
+    if let Ok(session) = read_session_from_file(&path) {
+        for output_ref in session.records.iter().filter_map(|r| r.output_ref.as_ref()) {
+            if let Err(err) = remove_file(output_ref) {
+                log::warn!("could not remove spilled output {}: {}", output_ref.display(), err);
+            }
+        }
+    }
+    remove_file(&path)
+        .with_context(|| format!("could not remove session file at {}", path.display()))
+}
+
+/// Reads the sessions at `offset..offset + limit` in the newest-first listing order.
+pub fn list_sessions_paged_from_dir(
+    dir: impl AsRef<Path>,
+    offset: usize,
+    limit: usize,
+) -> Result<Vec<Session>> {
+    let dir = dir.as_ref();
+    let names = list_session_names_from_dir(dir)?;
+    names.iter().skip(offset).take(limit).map(|name| read_session_from_dir(dir, name)).collect()
+}
+
+pub fn write_session(session: &Session) -> Result<()> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    write_session_to_dir(session_dir, session)
+}
+
 pub fn read_session(name: &str) -> Result<Session> {
     let session_dir = get_session_dir().context("could not locate session data directory")?;
-    let path = session_dir.join(format!("{}.json", name));
-    read_session_from_file(&path)
-        .with_context(|| format!("could not read session data from {}", path.display()))
+    read_session_from_dir(session_dir, name)
 }
 
 pub fn list_session_names() -> Result<Vec<String>> {
@@ -145,17 +620,21 @@ pub fn list_session_names() -> Result<Vec<String>> {
     list_session_names_from_dir(session_dir).context("could not list sessions in session directory")
 }
 
+pub fn list_sessions_paged(offset: usize, limit: usize) -> Result<Vec<Session>> {
+    let session_dir = get_session_dir().context("could not locate session data directory")?;
+    list_sessions_paged_from_dir(session_dir, offset, limit)
+}
+
 pub fn remove_session(name: &str) -> Result<()> {
     let session_dir = get_session_dir().context("could not locate session data directory")?;
-    let path = session_dir.join(format!("{}.json", name));
-    remove_file(&path)
-        .with_context(|| format!("could not remove session file at {}", path.display()))
+    remove_session_from_dir(session_dir, name)
 }
 
 #[cfg(test)]
 mod test {
 
     use chrono::Duration;
+    use rstest::rstest;
     use tempfile::TempDir;
 
     use super::*;
@@ -171,13 +650,16 @@ mod test {
                     command: "cmd1".into(),
                     output: "out1".into(),
                     status: CommandStatus::Succeeded,
+                    ..Default::default()
                 },
                 CommandRecord {
                     command: "cmd2".into(),
                     output: "out2".into(),
                     status: CommandStatus::Failed,
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
         let temp_dir = TempDir::new().unwrap();
@@ -190,17 +672,313 @@ mod test {
     }
 
     #[test]
-    fn test_list_sessions_from_dir() {
+    fn test_session_version() {
+        let now = DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        let session = Session::new(now, Vec::new(), &SessionKeyFormat::default());
+        assert_eq!(session.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+
+        let json = serde_json::to_string(&session).unwrap();
+        let read: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(session, read);
+    }
+
+    #[test]
+    fn test_session_version_legacy() {
+        let json = r#"{"name":"test","recorded_at":"2020-01-01T00:00:00Z","records":[]}"#;
+        let read: Session = serde_json::from_str(json).unwrap();
+        assert_eq!(read.version, None);
+        assert_eq!(read.source, None);
+        assert_eq!(read.tty_size, None);
+    }
+
+    #[test]
+    fn test_session_tty_size_serde() {
+        let now = DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        let session = Session {
+            tty_size: Some((120, 40)),
+            ..Session::new(now, Vec::new(), &SessionKeyFormat::default())
+        };
+
+        let json = serde_json::to_string(&session).unwrap();
+        assert!(json.contains(r#""tty_size":[120,40]"#));
+        let read: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.tty_size, Some((120, 40)));
+    }
+
+    #[rstest]
+    #[case::utf8(b"hello\n", r#"{"encoding":"utf8","data":"hello\n"}"#)]
+    #[case::nul(b"a\0b", r#"{"encoding":"utf8","data":"a\u0000b"}"#)]
+    #[case::binary(b"\xff\xfe\x00", r#"{"encoding":"base64","data":"//4A"}"#)]
+    fn test_command_record_output_serde(#[case] output: &[u8], #[case] json: &str) {
+        let record = CommandRecord {
+            command: "cmd".into(),
+            output: output.to_vec(),
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        };
+        let serialized = serde_json::to_string(&record).unwrap();
+        assert!(serialized.contains(&format!(r#""output":{}"#, json)));
+        assert_eq!(serde_json::from_str::<CommandRecord>(&serialized).unwrap(), record);
+    }
+
+    #[test]
+    fn test_spilled_output_serde() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let record = CommandRecord { output: b"large".to_vec(), ..Default::default() };
+        let mut session =
+            Session { name: "test".into(), records: vec![record], ..Default::default() };
+        session.spill_outputs(&dir.join("spill"), 4).unwrap();
+        session.load_spilled_outputs().unwrap();
+
+        // a loaded output is not written back into the session
+        write_session_to_dir(dir, &session).unwrap();
+        let stored = read_session_from_file(session_path_in_dir(dir, "test")).unwrap();
+        assert!(stored.records[0].output.is_empty());
+        let read = read_session_from_dir(dir, "test").unwrap();
+        assert_eq!(read.records[0].output, b"large");
+
+        remove_session_from_dir(dir, "test").unwrap();
+        assert!(!dir.join("spill/test-1.out").exists());
+    }
+
+    #[test]
+    fn test_command_record_output_legacy() {
+        let json = r#"{"command":"cmd","output":"hello\n","status":"succeeded"}"#;
+        let record: CommandRecord = serde_json::from_str(json).unwrap();
+        assert_eq!(record.output, b"hello\n");
+    }
+
+    #[test]
+    fn test_compute_output_hash() {
+        let record = |output: &[u8]| CommandRecord {
+            command: "cmd".into(),
+            output: output.to_vec(),
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        };
+        let hash = record(b"hello\n").compute_output_hash();
+        assert_eq!(hash, "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03");
+        assert_eq!(record(b"hello\n").compute_output_hash(), hash);
+        assert_ne!(record(b"hello").compute_output_hash(), hash);
+    }
+
+    #[rstest]
+    #[case::succeeded(CommandStatus::Succeeded, true, true, true, false, false)]
+    #[case::failed(CommandStatus::Failed, true, true, false, true, true)]
+    #[case::skipped(CommandStatus::Skipped, false, false, false, false, false)]
+    #[case::annotation(CommandStatus::Annotation, false, false, false, false, false)]
+    #[case::errored(CommandStatus::Errored, false, true, false, false, true)]
+    fn test_command_status(
+        #[case] status: CommandStatus,
+        #[case] executed: bool,
+        #[case] attempted: bool,
+        #[case] succeeded: bool,
+        #[case] failed: bool,
+        #[case] failed_or_errored: bool,
+    ) {
+        assert_eq!(status.is_executed(), executed);
+        assert_eq!(status.is_attempted(), attempted);
+        assert_eq!(status.is_succeeded(), succeeded);
+        assert_eq!(status.is_failed(), failed);
+        assert_eq!(status.is_failed_or_errored(), failed_or_errored);
+    }
+
+    #[rstest]
+    #[case::first_line(b"hello\nworld\n", Some("hello"))]
+    #[case::truncated(
+        b"0123456789012345678901234567890123456789012345\n",
+        Some("0123456789012345678901234567890123456...")
+    )]
+    #[case::empty(b"", None)]
+    fn test_session_summary_preview(#[case] output: &[u8], #[case] expected: Option<&str>) {
+        let record = CommandRecord {
+            command: "cmd".into(),
+            output: output.to_vec(),
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        };
+        let session = Session::new(Utc::now(), vec![record], &SessionKeyFormat::default());
+
+        let summary = session.summary(true);
+        assert_eq!(summary.records[0].output_preview.as_deref(), expected);
+        assert_eq!(session.summary(false).records[0].output_preview, None);
+    }
+
+    #[test]
+    fn test_size_warning() {
+        let mut session = Session::new(Utc::now(), Vec::new(), &SessionKeyFormat::default());
+        session.records.push(CommandRecord {
+            command: "cat large".into(),
+            output: vec![b'x'; 2048],
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        });
+        let size = serde_json::to_vec(&session).unwrap().len();
+        assert!(size_warning(&session, size, 1024).is_some());
+        assert!(size_warning(&session, size, DEFAULT_WARN_SIZE).is_none());
+    }
+
+    #[test]
+    fn test_generate_session_key_default() {
+        let now = DateTime::parse_from_rfc3339("2020-01-02T03:04:05.678Z").unwrap().into();
+        let key = generate_session_key(now, &SessionKeyFormat::default());
+        let (timestamp, suffix) = key.split_once('-').unwrap();
+        assert_eq!(timestamp, "20200102030405678");
+        assert_eq!(suffix.len(), 8);
+    }
+
+    #[rstest]
+    #[case::custom("%Y-%m-%dT%H%M%S", 4, "2020-01-02T030405-", 22)]
+    #[case::no_suffix("%Y%m%d%H%M%S", 0, "20200102030405", 14)]
+    fn test_generate_session_key_custom(
+        #[case] timestamp: &str,
+        #[case] suffix_len: usize,
+        #[case] prefix: &str,
+        #[case] len: usize,
+    ) {
+        let now = DateTime::parse_from_rfc3339("2020-01-02T03:04:05.678Z").unwrap().into();
+        let key_format = SessionKeyFormat { timestamp: timestamp.into(), suffix_len };
+        let key = generate_session_key(now, &key_format);
+        assert!(key.starts_with(prefix));
+        assert_eq!(key.len(), len);
+    }
+
+    #[rstest]
+    #[case::zero("0", Some(0))]
+    #[case::max("32", Some(32))]
+    #[case::too_long("33", None)]
+    #[case::negative("-1", None)]
+    #[case::not_number("eight", None)]
+    fn test_parse_suffix_len(#[case] len: &str, #[case] expected: Option<usize>) {
+        assert_eq!(parse_suffix_len(len).ok(), expected);
+    }
+
+    #[rstest]
+    #[case::default("%Y%m%d%H%M%S%3f", true)]
+    #[case::iso("%Y-%m-%dT%H:%M:%S%.3f", true)]
+    #[case::day_first("%d%m%Y%H%M%S%3f", false)]
+    #[case::seconds_only("%Y%m%d%H%M%S", false)]
+    fn test_is_chronological_format(#[case] format: &str, #[case] expected: bool) {
+        assert_eq!(is_chronological_format(format), expected);
+    }
+
+    #[rstest]
+    #[case::default("%Y%m%d%H%M%S%3f", true)]
+    #[case::invalid("%Y%Q", false)]
+    #[case::dangling("%Y%", false)]
+    #[case::slash("%Y/%m/%d", false)]
+    #[case::empty("", false)]
+    fn test_validate_timestamp_format(#[case] format: &str, #[case] valid: bool) {
+        assert_eq!(validate_timestamp_format(format).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_make_name_unique_in() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let session = || Session { name: "20200101000000".into(), ..Default::default() };
+
+        let mut first = session();
+        first.make_name_unique_in(dir);
+        write_session_to_dir(dir, &first).unwrap();
+        let mut second = session();
+        second.make_name_unique_in(dir);
+        write_session_to_dir(dir, &second).unwrap();
+        let mut third = session();
+        third.make_name_unique_in(dir);
+
+        assert_eq!(first.name, "20200101000000");
+        assert_eq!(second.name, "20200101000000-2");
+        assert_eq!(third.name, "20200101000000-3");
+    }
+
+    #[test]
+    fn test_session_source_serde() {
+        let source = SessionSource::File(vec!["deploy.sh".into()]);
+        let json = serde_json::to_string(&source).unwrap();
+        assert_eq!(json, r#"{"type":"file","value":["deploy.sh"]}"#);
+        assert_eq!(serde_json::from_str::<SessionSource>(&json).unwrap(), source);
+
+        let json = serde_json::to_string(&SessionSource::Stdin).unwrap();
+        assert_eq!(json, r#"{"type":"stdin"}"#);
+    }
+
+    #[test]
+    fn test_merge_sessions() {
         let now: DateTime<Utc> =
             DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        let record = |command: &str| CommandRecord {
+            command: command.into(),
+            output: Vec::new(),
+            status: CommandStatus::Succeeded,
+            ..Default::default()
+        };
         let session1 = Session {
             name: "test1".into(),
+            recorded_at: now.checked_add_signed(Duration::seconds(2)).unwrap(),
+            records: vec![record("cmd1a"), record("cmd1b")],
+            ..Default::default()
+        };
+        let session2 = Session {
+            name: "test2".into(),
             recorded_at: now.checked_add_signed(Duration::seconds(1)).unwrap(),
+            records: vec![record("cmd2a")],
+            ..Default::default()
+        };
+
+        let merged = merge_sessions(
+            vec![session1, session2],
+            Some("combined".into()),
+            false,
+            &SessionKeyFormat::default(),
+        )
+        .unwrap();
+        let commands: Vec<&str> = merged.records.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["cmd1a", "cmd1b", "cmd2a"]);
+        assert_eq!(merged.name, "combined");
+        assert_eq!(merged.recorded_at, now.checked_add_signed(Duration::seconds(1)).unwrap());
+    }
+
+    #[test]
+    fn test_merge_sessions_newest() {
+        let now: DateTime<Utc> =
+            DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        let later = now.checked_add_signed(Duration::seconds(1)).unwrap();
+        let sessions = vec![
+            Session {
+                name: "test1".into(),
+                recorded_at: now,
+                records: vec![],
+                ..Default::default()
+            },
+            Session {
+                name: "test2".into(),
+                recorded_at: later,
+                records: vec![],
+                ..Default::default()
+            },
+        ];
+
+        let merged = merge_sessions(sessions, None, true, &SessionKeyFormat::default()).unwrap();
+        assert_eq!(merged.recorded_at, later);
+        assert!(merge_sessions(Vec::new(), None, false, &SessionKeyFormat::default()).is_err());
+    }
+
+    #[test]
+    fn test_list_sessions_from_dir() {
+        let now: DateTime<Utc> =
+            DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+        let session1 = Session {
+            name: "test1".into(),
+            recorded_at: now.checked_add_signed(Duration::seconds(3)).unwrap(),
             records: vec![CommandRecord {
                 command: "cmd1".into(),
                 output: "out1".into(),
                 status: CommandStatus::Succeeded,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let session2 = Session {
             name: "test2".into(),
@@ -209,19 +987,24 @@ mod test {
                 command: "cmd2".into(),
                 output: "out2".into(),
                 status: CommandStatus::Failed,
+                ..Default::default()
             }],
+            ..Default::default()
         };
         let session3 = Session {
             name: "test3".into(),
-            recorded_at: now.checked_add_signed(Duration::seconds(3)).unwrap(),
+            recorded_at: now.checked_add_signed(Duration::seconds(1)).unwrap(),
             records: vec![CommandRecord {
                 command: "cmd3".into(),
                 output: "out3".into(),
                 status: CommandStatus::Failed,
+                ..Default::default()
             }],
+            ..Default::default()
         };
 
-        let expected = Some(vec!["3".into(), "2".into(), "1".into()]);
+        // name order and time order disagree; time order wins
+        let expected = Some(vec!["1".into(), "2".into(), "3".into()]);
 
         let temp_dir = TempDir::new().unwrap();
         let temp_path = temp_dir.path();
@@ -233,4 +1016,63 @@ mod test {
         let sessions = list_session_names_from_dir(temp_path);
         assert_eq!(expected, sessions.ok());
     }
+
+    #[test]
+    fn test_list_sessions_from_dir_without_parsing() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        std::fs::write(temp_path.join("20200101000002-b.json"), b"not a session").unwrap();
+        std::fs::write(temp_path.join("20200101000001-a.json"), b"").unwrap();
+        std::fs::write(temp_path.join("20200101000003-c.json"), b"{").unwrap();
+        std::fs::write(temp_path.join("ignored.txt"), b"").unwrap();
+        // the records after the timestamp are never read
+        let json = r#"{"name":"renamed","recorded_at":"2020-01-01T00:00:00Z","records":[garbage"#;
+        std::fs::write(temp_path.join("renamed.json"), json).unwrap();
+
+        let expected = Some(vec![
+            "renamed".into(),
+            "20200101000003-c".into(),
+            "20200101000002-b".into(),
+            "20200101000001-a".into(),
+        ]);
+        let sessions = list_session_names_from_dir(temp_path);
+        assert_eq!(expected, sessions.ok());
+    }
+
+    #[rstest]
+    #[case::plain("20200101000000000-0123abcd", true)]
+    #[case::empty("", false)]
+    #[case::parent("..", false)]
+    #[case::traversal("../../.bashrc", false)]
+    #[case::slash("a/b", false)]
+    #[case::backslash("a\\b", false)]
+    fn test_validate_session_name(#[case] name: &str, #[case] valid: bool) {
+        assert_eq!(validate_session_name(name).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_list_sessions_paged_from_dir() {
+        let now: DateTime<Utc> =
+            DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z").unwrap().into();
+
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        for i in 1..=5 {
+            let session = Session {
+                name: format!("test{}", i),
+                recorded_at: now.checked_add_signed(Duration::seconds(i)).unwrap(),
+                records: vec![],
+                ..Default::default()
+            };
+            write_session_to_dir(temp_path, &session).unwrap();
+        }
+
+        let sessions = list_sessions_paged_from_dir(temp_path, 2, 2).unwrap();
+        let names: Vec<&str> = sessions.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["test3", "test2"]);
+
+        let sessions = list_sessions_paged_from_dir(temp_path, 4, 10).unwrap();
+        assert_eq!(sessions.len(), 1);
+    }
 }
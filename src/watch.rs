@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+type Snapshot = BTreeMap<PathBuf, Option<SystemTime>>;
+
+/// Directories are walked at most this many levels below a watched path.
+const MAX_DEPTH: usize = 8;
+
+fn collect_mtimes(path: &Path, depth: usize, snapshot: &mut Snapshot) {
+    let metadata = match path.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => {
+            snapshot.insert(path.to_owned(), None);
+            return;
+        }
+    };
+    if !metadata.is_dir() {
+        snapshot.insert(path.to_owned(), metadata.modified().ok());
+        return;
+    }
+    if depth == MAX_DEPTH {
+        return;
+    }
+    if let Ok(entries) = path.read_dir() {
+        for entry in entries.flatten() {
+            collect_mtimes(&entry.path(), depth + 1, snapshot);
+        }
+    }
+}
+
+/// Watches files and directories (recursively) by polling their modification times, standing in
+/// for OS file notifications as the notify crate is not available. Each poll walks the watched
+/// directories up to `MAX_DEPTH` levels deep.
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    snapshot: Snapshot,
+}
+
+impl Watcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let mut watcher = Watcher { paths, snapshot: Snapshot::new() };
+        watcher.snapshot = watcher.take_snapshot();
+        watcher
+    }
+
+    fn take_snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        for path in &self.paths {
+            collect_mtimes(path, 0, &mut snapshot);
+        }
+        snapshot
+    }
+
+    /// Returns the paths changed since the last call, or since the watcher was created.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let snapshot = self.take_snapshot();
+        let mut changed: Vec<PathBuf> = snapshot
+            .iter()
+            .filter(|(path, mtime)| self.snapshot.get(*path) != Some(*mtime))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(self.snapshot.keys().filter(|p| !snapshot.contains_key(*p)).cloned());
+        self.snapshot = snapshot;
+        changed
+    }
+
+    /// Blocks until a change is observed and no further change follows within `debounce`, or
+    /// at most `settle` after the first change if the files keep changing.
+    pub fn wait(&mut self, debounce: Duration, settle: Duration) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        while changed.is_empty() {
+            sleep(debounce);
+            changed = self.poll();
+        }
+        let deadline = Instant::now() + settle;
+        while Instant::now() < deadline {
+            sleep(debounce);
+            let more = self.poll();
+            if more.is_empty() {
+                break;
+            }
+            changed.extend(more);
+        }
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir, remove_file, write, File};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn touch(path: &Path, secs: u64) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    }
+
+    #[test]
+    fn test_watcher_poll() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        let script = temp_path.join("script.sh");
+        let dir = temp_path.join("dir");
+        write(&script, b"echo hi\n").unwrap();
+        create_dir(&dir).unwrap();
+        write(dir.join("a"), b"").unwrap();
+
+        let mut watcher = Watcher::new(vec![script.clone(), dir.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        touch(&script, 1);
+        assert_eq!(watcher.poll(), vec![script.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        write(dir.join("b"), b"").unwrap();
+        assert_eq!(watcher.poll(), vec![dir.join("b")]);
+
+        remove_file(dir.join("a")).unwrap();
+        assert_eq!(watcher.poll(), vec![dir.join("a")]);
+    }
+
+    #[test]
+    fn test_watcher_max_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut dir = temp_dir.path().to_owned();
+        for _ in 0..MAX_DEPTH {
+            dir = dir.join("d");
+            create_dir(&dir).unwrap();
+        }
+
+        let mut watcher = Watcher::new(vec![temp_dir.path().to_owned()]);
+        write(dir.join("too-deep"), b"").unwrap();
+        assert!(watcher.poll().is_empty());
+        write(dir.parent().unwrap().join("shallow"), b"").unwrap();
+        assert_eq!(watcher.poll(), vec![dir.parent().unwrap().join("shallow")]);
+    }
+
+    #[test]
+    fn test_watcher_wait_settle() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("busy");
+        write(&path, b"").unwrap();
+
+        let mut watcher = Watcher::new(vec![path.clone()]);
+        let started = Instant::now();
+        std::thread::scope(|scope| {
+            // keeps changing the file for longer than the settle time
+            scope.spawn(|| {
+                for secs in 1..100 {
+                    touch(&path, secs);
+                    sleep(Duration::from_millis(10));
+                }
+            });
+            let changed = watcher.wait(Duration::from_millis(20), Duration::from_millis(100));
+            assert_eq!(changed, vec![path.clone()]);
+            assert!(started.elapsed() < Duration::from_millis(500));
+        });
+    }
+}